@@ -1,5 +1,5 @@
 import { describe, expect, it } from 'vitest';
-import { compileCSS } from '../../index';
+import { compileCSS, compileCSSAsync } from '../../index';
 import path from 'node:path';
 
 describe('compileCss', () => {
@@ -16,7 +16,48 @@ describe('compileCss', () => {
         "a.css",
         "b.css",
         "c.css",
+        "img.png",
       ]
     `);
   });
+
+  it('should compile css async', async () => {
+    expect(await compileCSSAsync(entry)).toEqual(compileCSS(entry));
+  });
+
+  it('should report errors thrown by hooks', () => {
+    const read = () => {
+      throw new Error('read failed');
+    };
+    const rewriteUrl = () => {
+      throw new Error('rewrite failed');
+    };
+    expect(() => compileCSS(entry, { read })).toThrow(
+      expect.objectContaining({
+        diagnostic: expect.objectContaining({ message: 'JS `read` hook threw: read failed' }),
+      }),
+    );
+    expect(() => compileCSS(entry, { rewriteUrl })).toThrow(
+      expect.objectContaining({
+        diagnostic: expect.objectContaining({ message: 'JS `rewriteUrl` hook threw: rewrite failed' }),
+      }),
+    );
+    // 异常已经清除, 之后的调用不受影响
+    expect(compileCSS(entry, { read: () => null }).css).toEqual(compileCSS(entry).css);
+  });
+
+  it('should report errors thrown by hooks async', async () => {
+    const resolve = () => {
+      throw new Error('resolve failed');
+    };
+    await expect(compileCSSAsync(entry, { resolve })).rejects.toMatchObject({
+      diagnostic: { message: 'JS `resolve` hook threw: resolve failed' },
+    });
+    const rewriteUrl = () => {
+      throw 'rewrite failed';
+    };
+    await expect(compileCSSAsync(entry, { rewriteUrl })).rejects.toMatchObject({
+      diagnostic: { message: 'JS `rewriteUrl` hook threw: rewrite failed' },
+    });
+  });
 });
@@ -1,6 +1,6 @@
 import { describe, expect, it } from 'vitest';
 
-import { styleFactory } from '../index.js';
+import { styleFactory, styleFactoryAsync } from '../index.js';
 
 import * as bundle from '../index';
 console.log(bundle);
@@ -79,4 +79,12 @@ describe('styleFactory', () => {
       styleFactory(`.a color: #ff0000; }`, { throwOnError: true });
     }).toThrowErrorMatchingInlineSnapshot(`[Error: Transform error: Parse error: Unexpected end of input at :0:21]`);
   });
+
+  it('async matches sync', async () => {
+    const css = `.a { height: 100rpx; }`;
+    expect(await styleFactoryAsync(css)).toBe(styleFactory(css));
+    await expect(styleFactoryAsync(`.a color: #ff0000; }`)).rejects.toThrowErrorMatchingInlineSnapshot(
+      `[Error: Transform error: Parse error: Unexpected end of input at :0:21]`,
+    );
+  });
 });
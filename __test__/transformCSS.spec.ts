@@ -1,4 +1,4 @@
-import {transformCSS} from '../'
+import {transformCSS, transformCSSAsync} from '../'
 
 import {describe, expect, it} from 'vitest';
 
@@ -86,4 +86,13 @@ describe('transformCSS', () => {
       expect(e).toMatchInlineSnapshot(`[Error: @import rules must precede all rules aside from @charset and @layer statements at :5:16]`)
     }
   });
-})
\ No newline at end of file
+
+  it('async matches sync', async () => {
+    const options = { input: `.c { color: green; backdrop-filter: blur(2px); }`, minify: true }
+    expect(await transformCSSAsync(options)).toEqual(transformCSS(options))
+    await expect(transformCSSAsync({ input: `.c color: green; }` })).rejects.toHaveProperty(
+      'diagnostic.kind',
+      'parse',
+    )
+  });
+})
@@ -3,18 +3,145 @@
 
 /* auto-generated by NAPI-RS */
 
+export interface CompileStyleFactoryResult {
+  code: string
+  map?: string
+  warnings: Array<JsDiagnostic>
+  dts?: string
+  classNames?: Array<string>
+  css?: string
+  dependencies: Array<string>
+  imports: Record<string, Array<string>>
+  externals: Array<string>
+  assets: Array<JsAsset>
+}
+export declare function compileStyleFactory(entry: string, options?: StyleFactoryOptions | undefined | null, compileOptions?: JsCompileCssOptions | undefined | null): CompileStyleFactoryResult
+export declare function compileStyleFactoryAsync(entry: string, options?: StyleFactoryOptions | undefined | null, compileOptions?: JsCompileCssOptions | undefined | null): Promise<CompileStyleFactoryResult>
+export interface JsAsset {
+  url: string
+  path?: string
+  file: string
+  line: number
+  column: number
+}
 export interface JsCompileResult {
   css: string
   dependencies: Array<string>
   imports: Record<string, Array<string>>
+  warnings: Array<JsDiagnostic>
+  externals: Array<string>
+  assets: Array<JsAsset>
+}
+export interface JsCompileCssOptions {
+  targets?: JsTargets
+  errorRecovery?: boolean
+  alias?: Record<string, string>
+  moduleDirectories?: Array<string>
+  extensions?: Array<string>
+  mainFields?: Array<string>
+  files?: Record<string, string>
+  readFromDisk?: boolean
+  read?: (path: string) => string | null | undefined
+  resolve?: (specifier: string, originatingFile: string) => string | null | undefined
+  externals?: Array<string>
+  publicPath?: string
+  rewriteUrl?: (asset: JsAsset) => string | null | undefined
+  inlineLimit?: number
+}
+export declare function compileCSS(entry: string, options?: JsCompileCssOptions | undefined | null): JsCompileResult
+export declare function compileCSSAsync(entry: string, options?: JsCompileCssOptions | undefined | null): Promise<JsCompileResult>
+export interface JsDiagnostic {
+  kind: string
+  severity: string
+  message: string
+  file?: string
+  line?: number
+  column?: number
+  codeFrame?: string
+}
+export interface JsTargets {
+  browserslist?: Array<string>
+  browsers?: Record<string, string>
 }
-export declare function compileCSS(entry: string): JsCompileResult
 export interface JsTransformCssResult {
   code: string
+  warnings: Array<JsDiagnostic>
 }
 export interface JsTransformCssOptions {
   input: string
   minify?: boolean
+  targets?: JsTargets
+  errorRecovery?: boolean
 }
 export declare function transformCSS(option?: JsTransformCssOptions | undefined | null): JsTransformCssResult
-export declare function styleFactory(cssText: string): string
+export declare function transformCSSAsync(option?: JsTransformCssOptions | undefined | null): Promise<JsTransformCssResult>
+export interface JsUnitConversion {
+  runtime?: string
+  factor?: number
+  unit?: string
+}
+export interface JsStaticCssOptions {
+  prefix?: string
+  host?: string
+  unitFactors?: Record<string, number>
+}
+export interface StyleFactoryOptions {
+  targets?: JsTargets
+  prefixClass?: boolean
+  replaceRpx?: boolean
+  units?: Record<string, JsUnitConversion>
+  designWidth?: number
+  deviceWidth?: number
+  replaceTag?: boolean
+  runtimeTag?: boolean
+  replaceStar?: boolean
+  replaceWebView?: boolean
+  metaTag?: string
+  unsupportedStar?: string
+  unsupportedWebView?: string
+  tagMap?: Record<string, string>
+  sourceMap?: boolean
+  filename?: string
+  errorRecovery?: boolean
+  format?: string
+  globalName?: string
+  dts?: boolean
+  classNames?: boolean
+  exportClasses?: boolean
+  hashClass?: boolean
+  classNamePattern?: string
+  staticCss?: JsStaticCssOptions
+  publicPath?: string
+  assets?: boolean
+}
+export interface StyleFactoryResult {
+  code: string
+  map?: string
+  warnings: Array<JsDiagnostic>
+  dts?: string
+  classNames?: Array<string>
+  css?: string
+  assets?: Array<JsAsset>
+}
+export declare function styleFactory(cssText: string, options?: StyleFactoryOptions | undefined | null): string
+export declare function styleFactoryResult(cssText: string, options?: StyleFactoryOptions | undefined | null): StyleFactoryResult
+export declare function styleFactoryAsync(cssText: string, options?: StyleFactoryOptions | undefined | null): Promise<string>
+export declare function styleFactoryResultAsync(cssText: string, options?: StyleFactoryOptions | undefined | null): Promise<StyleFactoryResult>
+export interface StyleFactoryBatchInput {
+  id: string
+  css: string
+  options?: StyleFactoryOptions
+}
+export interface StyleFactoryBatchResult {
+  id: string
+  code?: string
+  map?: string
+  warnings?: Array<JsDiagnostic>
+  dts?: string
+  classNames?: Array<string>
+  css?: string
+  assets?: Array<JsAsset>
+  error?: string
+  diagnostic?: JsDiagnostic
+}
+export declare function styleFactoryBatch(inputs: Array<StyleFactoryBatchInput>): Array<StyleFactoryBatchResult>
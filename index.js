@@ -310,8 +310,16 @@ if (!nativeBinding) {
   throw new Error(`Failed to load native binding`)
 }
 
-const { compileCSS, transformCSS, styleFactory } = nativeBinding
+const { compileStyleFactory, compileStyleFactoryAsync, compileCSS, compileCSSAsync, transformCSS, transformCSSAsync, styleFactory, styleFactoryResult, styleFactoryAsync, styleFactoryResultAsync, styleFactoryBatch } = nativeBinding
 
+module.exports.compileStyleFactory = compileStyleFactory
+module.exports.compileStyleFactoryAsync = compileStyleFactoryAsync
 module.exports.compileCSS = compileCSS
+module.exports.compileCSSAsync = compileCSSAsync
 module.exports.transformCSS = transformCSS
+module.exports.transformCSSAsync = transformCSSAsync
 module.exports.styleFactory = styleFactory
+module.exports.styleFactoryResult = styleFactoryResult
+module.exports.styleFactoryAsync = styleFactoryAsync
+module.exports.styleFactoryResultAsync = styleFactoryResultAsync
+module.exports.styleFactoryBatch = styleFactoryBatch
@@ -0,0 +1,318 @@
+use crate::diagnostic::{Diagnostic, DiagnosticKind};
+use crate::node_path::normalize;
+use crate::resolver::FileSystem;
+use data_encoding::BASE64;
+use lazy_regex::{lazy_regex, Lazy, Regex};
+use lightningcss::rules::CssRule;
+use lightningcss::values::url::Url;
+use lightningcss::visitor::{Visit, VisitTypes, Visitor};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// 带协议的地址不是本地文件  data:  https://  //cdn.com
+static URL_SCHEME_REGEX: Lazy<Regex> = lazy_regex!(r"^(?:[a-zA-Z][a-zA-Z\d+.\-]*:|//)");
+
+/*
+ * 样式中 url() 引用的资源
+ * path: 相对地址按所在文件解析后的路径, 远程地址 / data: / 根路径 / #id 时为 None
+ * line / column: 在所在文件中的位置, 从 1 开始
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Asset {
+  pub url: String,
+  pub path: Option<PathBuf>,
+  pub file: String,
+  pub line: u32,
+  pub column: u32,
+}
+
+impl Asset {
+  pub fn new(url: &str, file: &str, line: u32, column: u32) -> Self {
+    Asset {
+      url: url.to_string(),
+      path: resolve_asset_path(url, file),
+      file: file.to_string(),
+      line,
+      column,
+    }
+  }
+}
+
+// 返回 None 时保持原地址
+pub type RewriteUrl = Arc<dyn Fn(&Asset) -> Result<Option<String>, String> + Send + Sync>;
+
+#[derive(Clone, Default)]
+pub struct AssetOptions {
+  // 本地资源改写为 public_path + 相对于根目录的路径
+  pub public_path: Option<String>,
+  // 优先于 public_path
+  pub rewrite: Option<RewriteUrl>,
+}
+
+impl fmt::Debug for AssetOptions {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("AssetOptions")
+      .field("public_path", &self.public_path)
+      .field("rewrite", &self.rewrite.is_some())
+      .finish()
+  }
+}
+
+impl AssetOptions {
+  /*
+   * 收集并改写 url
+   * @param root: public_path 的根目录, 单个文件时为文件所在目录, 打包时为入口所在目录
+   * @return 根目录外的资源没有对应的 public 地址, 保持原地址并返回警告
+   */
+  pub fn apply(
+    &self,
+    url: &mut Url,
+    asset: &Asset,
+    root: &Path,
+  ) -> Result<Option<Diagnostic>, String> {
+    if let Some(rewrite) = &self.rewrite {
+      if let Some(rewritten) = rewrite(asset)? {
+        url.url = rewritten.into();
+        return Ok(None);
+      }
+    }
+    if let (Some(public_path), Some(path)) = (&self.public_path, &asset.path) {
+      match public_url(public_path, path, root, &asset.url) {
+        Some(public) => url.url = public.into(),
+        None => return Ok(Some(outside_root_warning(asset, root))),
+      }
+    }
+    Ok(None)
+  }
+}
+
+fn resolve_asset_path(url: &str, file: &str) -> Option<PathBuf> {
+  if file.is_empty()
+    || url.is_empty()
+    || url.starts_with('#')
+    || url.starts_with('/')
+    || URL_SCHEME_REGEX.is_match(url)
+  {
+    return None;
+  }
+  let path = &url[..url.find(['?', '#']).unwrap_or(url.len())];
+  let dir = Path::new(file).parent().unwrap_or(Path::new(""));
+  Some(normalize(dir.join(path)))
+}
+
+// ./images/a.png?v=1 => https://cdn.com/images/a.png?v=1, 不在根目录下时返回 None
+fn public_url(public_path: &str, path: &Path, root: &Path, url: &str) -> Option<String> {
+  let suffix = &url[url.find(['?', '#']).unwrap_or(url.len())..];
+  let relative = path
+    .strip_prefix(normalize(root))
+    .ok()?
+    .components()
+    .map(|c| c.as_os_str().to_string_lossy())
+    .collect::<Vec<_>>()
+    .join("/");
+  Some(format!(
+    "{}/{}{}",
+    public_path.trim_end_matches('/'),
+    relative,
+    suffix
+  ))
+}
+
+fn outside_root_warning(asset: &Asset, root: &Path) -> Diagnostic {
+  let mut diagnostic = Diagnostic::new(
+    DiagnosticKind::InvalidOption,
+    format!(
+      "url({}) is outside the publicPath root `{}`, kept unchanged",
+      asset.url,
+      root.display()
+    ),
+  )
+  .warning();
+  diagnostic.file = (!asset.file.is_empty()).then(|| asset.file.clone());
+  diagnostic.line = Some(asset.line);
+  diagnostic.column = Some(asset.column);
+  diagnostic
+}
+
+// 按扩展名确定 data: 的 MIME 类型, 未知的类型不内联
+fn mime_type(path: &Path) -> Option<&'static str> {
+  let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+  Some(match extension.as_str() {
+    "png" => "image/png",
+    "jpg" | "jpeg" => "image/jpeg",
+    "gif" => "image/gif",
+    "webp" => "image/webp",
+    "avif" => "image/avif",
+    "svg" => "image/svg+xml",
+    "ico" => "image/x-icon",
+    "bmp" => "image/bmp",
+    "woff" => "font/woff",
+    "woff2" => "font/woff2",
+    "ttf" => "font/ttf",
+    "otf" => "font/otf",
+    "eot" => "application/vnd.ms-fontobject",
+    _ => return None,
+  })
+}
+
+/*
+ * 小于 limit 字节的本地资源内联为 base64 的 data: 地址
+ * 文件不存在或类型未知时保持原地址
+ */
+pub struct InlineAssets<'a> {
+  pub fs: &'a dyn FileSystem,
+  pub limit: usize,
+}
+
+impl InlineAssets<'_> {
+  fn data_url(&self, asset: &Asset) -> Option<String> {
+    let path = asset.path.as_ref()?;
+    let mime = mime_type(path)?;
+    // 先判断大小, 超过 limit 的文件不读取
+    if self.fs.size(path).ok()? >= self.limit as u64 {
+      return None;
+    }
+    let data = self.fs.read(path).ok()?;
+    Some(format!("data:{};base64,{}", mime, BASE64.encode(&data)))
+  }
+}
+
+/*
+ * 打包后的样式包含多个文件, 按规则的 source_index 确定 url 所在的文件
+ */
+pub struct AssetVisitor<'a> {
+  sources: &'a [String],
+  source_index: u32,
+  options: &'a AssetOptions,
+  root: &'a Path,
+  inline: Option<InlineAssets<'a>>,
+  pub assets: Vec<Asset>,
+  pub warnings: Vec<Diagnostic>,
+}
+
+impl<'a> AssetVisitor<'a> {
+  pub fn new(sources: &'a [String], options: &'a AssetOptions, root: &'a Path) -> Self {
+    AssetVisitor {
+      sources,
+      source_index: 0,
+      options,
+      root,
+      inline: None,
+      assets: Vec::new(),
+      warnings: Vec::new(),
+    }
+  }
+
+  // 内联优先于 rewrite 和 public_path
+  pub fn with_inline(mut self, inline: InlineAssets<'a>) -> Self {
+    self.inline = Some(inline);
+    self
+  }
+}
+
+impl<'i> Visitor<'i> for AssetVisitor<'_> {
+  type Error = String;
+
+  fn visit_types(&self) -> VisitTypes {
+    VisitTypes::RULES | VisitTypes::URLS
+  }
+
+  fn visit_rule(&mut self, rule: &mut CssRule<'i>) -> Result<(), Self::Error> {
+    if let Some(source_index) = rule_source_index(rule) {
+      self.source_index = source_index;
+    }
+    rule.visit_children(self)
+  }
+
+  fn visit_url(&mut self, url: &mut Url<'i>) -> Result<(), Self::Error> {
+    let file = self
+      .sources
+      .get(self.source_index as usize)
+      .map(String::as_str)
+      .unwrap_or_default();
+    // url 的 loc 行列都从 1 开始
+    let asset = Asset::new(&url.url, file, url.loc.line, url.loc.column);
+    match self
+      .inline
+      .as_ref()
+      .and_then(|inline| inline.data_url(&asset))
+    {
+      Some(data_url) => url.url = data_url.into(),
+      None => self
+        .warnings
+        .extend(self.options.apply(url, &asset, self.root)?),
+    }
+    self.assets.push(asset);
+    Ok(())
+  }
+}
+
+// 可能包含 url() 的规则
+fn rule_source_index(rule: &CssRule) -> Option<u32> {
+  let loc = match rule {
+    CssRule::Style(rule) => rule.loc,
+    CssRule::Media(rule) => rule.loc,
+    CssRule::Supports(rule) => rule.loc,
+    CssRule::FontFace(rule) => rule.loc,
+    CssRule::Keyframes(rule) => rule.loc,
+    CssRule::Page(rule) => rule.loc,
+    CssRule::LayerBlock(rule) => rule.loc,
+    CssRule::Container(rule) => rule.loc,
+    CssRule::Nesting(rule) => rule.loc,
+    CssRule::CounterStyle(rule) => rule.loc,
+    CssRule::Property(rule) => rule.loc,
+    _ => return None,
+  };
+  Some(loc.source_index)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::memory_provider::MemoryFiles;
+
+  #[test]
+  fn test_asset_path() {
+    let asset = |url: &str| Asset::new(url, "/project/src/a.css", 1, 1).path;
+    assert_eq!(
+      asset("./images/a.png?v=1#x"),
+      Some(PathBuf::from("/project/src/images/a.png"))
+    );
+    assert_eq!(asset("../b.png"), Some(PathBuf::from("/project/b.png")));
+    assert_eq!(asset("c.svg"), Some(PathBuf::from("/project/src/c.svg")));
+    assert_eq!(asset("/static/a.png"), None);
+    assert_eq!(asset("https://cdn.com/a.png"), None);
+    assert_eq!(asset("//cdn.com/a.png"), None);
+    assert_eq!(asset("data:image/png;base64,AAAA"), None);
+    assert_eq!(asset("#gradient"), None);
+    assert_eq!(Asset::new("./a.png", "", 1, 1).path, None);
+
+    let root = Path::new("/project/src");
+    let public = |url: &str, path: &str| public_url("https://cdn.com/", Path::new(path), root, url);
+    assert_eq!(
+      public("./images/a.png?v=1", "/project/src/images/a.png"),
+      Some("https://cdn.com/images/a.png?v=1".to_string())
+    );
+    // 根目录外的资源不能改写到 publicPath 之外
+    assert_eq!(public("../b.png", "/project/b.png"), None);
+  }
+
+  #[test]
+  fn test_inline_assets() {
+    let fs = MemoryFiles::new(
+      [(PathBuf::from("/project/src/a.svg"), "<svg/>".to_string())],
+      false,
+    );
+    let data_url = |url: &str, limit: usize| {
+      InlineAssets { fs: &fs, limit }.data_url(&Asset::new(url, "/project/src/a.css", 1, 1))
+    };
+    assert_eq!(
+      data_url("./a.svg", 7),
+      Some("data:image/svg+xml;base64,PHN2Zy8+".to_string())
+    );
+    // 等于 limit 或文件不存在时不内联
+    assert_eq!(data_url("./a.svg", 6), None);
+    assert_eq!(data_url("./b.svg", 1024), None);
+  }
+}
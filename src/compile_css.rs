@@ -1,45 +1,307 @@
-use crate::options::{get_parser_options, get_printer_options};
-use lightningcss::bundler::{Bundler, FileProvider, SourceProvider};
+use crate::assets::{Asset, AssetOptions, AssetVisitor, InlineAssets};
+use crate::diagnostic::{Diagnostic, DiagnosticKind};
+use crate::memory_provider::{MemoryFiles, MemoryProvider};
+use crate::options::{get_parser_options, get_printer_options, get_targets};
+use crate::resolver::{FileSystem, OsFileSystem, Resolver};
+use crate::source_map::{offset_to_position, position_to_offset, TrackedText};
+use lazy_regex::{lazy_regex, Lazy, Regex};
+use lightningcss::bundler::{BundleErrorKind, Bundler, FileProvider, SourceProvider};
+use lightningcss::media_query::MediaList;
+use lightningcss::properties::custom::{Token, TokenOrValue};
+use lightningcss::rules::import::ImportRule;
+use lightningcss::rules::Location;
+use lightningcss::rules::{CssRule, CssRuleList};
+use lightningcss::stylesheet::{ParserOptions, PrinterOptions};
+use lightningcss::targets::Targets;
+use lightningcss::traits::ToCss;
+use lightningcss::visitor::Visit;
+use once_cell::sync::OnceCell;
+use parcel_sourcemap::{OriginalLocation, SourceMap};
 use std::collections::HashMap;
 use std::error::Error;
-use std::io::{Error as IoError, ErrorKind};
+use std::io;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, RwLock};
 
+// 读取文件后对源码的预处理, 记录替换用于还原 source map 中的位置
+pub type Preprocess = Arc<dyn Fn(&str) -> TrackedText + Send + Sync>;
+
+// 自定义的读取和解析, 返回 None 时使用默认的实现
+pub type ReadHook = Arc<dyn Fn(&Path) -> Result<Option<String>, String> + Send + Sync>;
+pub type ResolveHook = Arc<dyn Fn(&str, &Path) -> Result<Option<PathBuf>, String> + Send + Sync>;
+
+#[derive(Clone, Default)]
+pub struct SourceHooks {
+  pub read: Option<ReadHook>,
+  pub resolve: Option<ResolveHook>,
+}
+
+impl SourceHooks {
+  fn is_empty(&self) -> bool {
+    self.read.is_none() && self.resolve.is_none()
+  }
+}
+
+// 远程地址不参与打包  https://a.com/b.css  //a.com/b.css
+static REMOTE_URL_REGEX: Lazy<Regex> = lazy_regex!(r"^(?:[a-zA-Z][a-zA-Z\d+.\-]*:)?//");
+
+// 外部引入解析为虚拟路径 external:<编号>, 读取时返回占位规则
+const EXTERNAL_PATH_PREFIX: &str = "external:";
+const EXTERNAL_AT_RULE: &str = "style-factory-external";
+
 #[derive(Debug)]
 pub struct CompileResult {
   pub css: String,
   pub dependencies: Vec<PathBuf>,
   pub imports: HashMap<PathBuf, Vec<PathBuf>>,
+  pub warnings: Vec<Diagnostic>,
+  // 保留为 @import 的外部引入
+  pub externals: Vec<String>,
+  // url() 引用的资源, 本地文件同时加入 dependencies
+  pub assets: Vec<Asset>,
+  // css 到各个源文件的 source map, 源文件路径相对于入口所在目录
+  pub source_map: Option<SourceMap>,
+}
+
+#[derive(Clone)]
+pub struct CompileCssOptions {
+  pub targets: Targets,
+  // 跳过无效的规则和声明, 并收集为 warnings
+  pub error_recovery: bool,
+  // 解析前对每个文件的源码做预处理
+  pub preprocess: Option<Preprocess>,
+  // @import 的路径解析: 别名 / node_modules / 扩展名
+  pub resolver: Resolver,
+  // 优先从内存中读取文件
+  pub files: Option<Arc<MemoryFiles>>,
+  // 优先于 files 和 resolver
+  pub hooks: SourceHooks,
+  // 匹配的 @import 不打包, 保留在输出的顶部, 远程地址总是外部引入
+  pub externals: Vec<Regex>,
+  // url() 的改写, public_path 相对于入口所在目录
+  pub assets: AssetOptions,
+  // 小于该字节数的 url() 资源内联为 data: 地址
+  pub inline_limit: Option<usize>,
+  pub source_map: bool,
+}
+
+impl Default for CompileCssOptions {
+  fn default() -> Self {
+    CompileCssOptions {
+      targets: get_targets(),
+      error_recovery: false,
+      preprocess: None,
+      resolver: Resolver::default(),
+      files: None,
+      hooks: SourceHooks::default(),
+      externals: Vec::new(),
+      assets: AssetOptions::default(),
+      inline_limit: None,
+      source_map: false,
+    }
+  }
 }
 
-struct TrackingProvider {
+/*
+ * 只追加的源码存储, 返回的 &str 在 provider 销毁前保持有效
+ * Bundler 会在多个线程中调用 read, 写入冲突时顺延到下一个节点
+ */
+#[derive(Default)]
+struct SourceStore {
+  source: OnceCell<String>,
+  next: OnceCell<Box<SourceStore>>,
+}
+
+impl SourceStore {
+  fn push(&self, mut source: String) -> &str {
+    let mut node = self;
+    loop {
+      match node.source.set(source) {
+        Ok(()) => return node.source.get().unwrap(),
+        Err(rejected) => {
+          source = rejected;
+          node = node.next.get_or_init(Box::default);
+        }
+      }
+    }
+  }
+}
+
+// 从磁盘读取文件
+struct DiskProvider {
   file_provider: FileProvider,
+  resolver: Resolver,
+}
+
+impl SourceProvider for DiskProvider {
+  type Error = io::Error;
+
+  fn read(&self, path: &Path) -> Result<&str, Self::Error> {
+    self.file_provider.read(path)
+  }
+
+  fn resolve(&self, specifier: &str, originating_file: &Path) -> Result<PathBuf, Self::Error> {
+    self
+      .resolver
+      .resolve(specifier, originating_file, &OsFileSystem)
+  }
+}
+
+type BoxedProvider = Box<dyn SourceProvider<Error = io::Error>>;
+
+// 先调用 hooks, 返回 None 时交给内层的 provider
+struct HookProvider {
+  hooks: SourceHooks,
+  provider: BoxedProvider,
+  sources: SourceStore,
+}
+
+impl SourceProvider for HookProvider {
+  type Error = io::Error;
+
+  fn read(&self, path: &Path) -> Result<&str, Self::Error> {
+    if let Some(read) = &self.hooks.read {
+      if let Some(source) = read(path).map_err(io::Error::other)? {
+        return Ok(self.sources.push(source));
+      }
+    }
+    self.provider.read(path)
+  }
+
+  fn resolve(&self, specifier: &str, originating_file: &Path) -> Result<PathBuf, Self::Error> {
+    if let Some(resolve) = &self.hooks.resolve {
+      if let Some(path) = resolve(specifier, originating_file).map_err(io::Error::other)? {
+        return Ok(path);
+      }
+    }
+    self.provider.resolve(specifier, originating_file)
+  }
+}
+
+// 原始源码, 以及预处理后的文本, 用于还原 source map 中的位置
+struct SourceRecord<'a> {
+  source: &'a str,
+  preprocessed: Option<TrackedText>,
+}
+
+/*
+ * 根据选项创建实际读取文件的 provider
+ */
+fn source_provider(options: &CompileCssOptions) -> BoxedProvider {
+  let resolver = options.resolver.clone();
+  let provider: BoxedProvider = match &options.files {
+    Some(files) => Box::new(MemoryProvider::new(files.clone(), resolver)),
+    None => Box::new(DiskProvider {
+      file_provider: FileProvider::new(),
+      resolver,
+    }),
+  };
+  if options.hooks.is_empty() {
+    return provider;
+  }
+  Box::new(HookProvider {
+    hooks: options.hooks.clone(),
+    provider,
+    sources: SourceStore::default(),
+  })
+}
+
+// 记录读取的文件和 @import 关系, 实际的读取和解析交给 provider
+struct TrackingProvider<'a> {
+  provider: &'a BoxedProvider,
   dependencies: Arc<Mutex<Vec<PathBuf>>>,
   imports: Arc<RwLock<HashMap<PathBuf, Vec<PathBuf>>>>,
+  preprocess: Option<Preprocess>,
+  preprocessed: SourceStore,
+  externals: Vec<Regex>,
+  // 外部引入的 specifier, 下标即虚拟路径中的编号
+  external_specifiers: Mutex<Vec<String>>,
+  // 开启 source map 时记录每个文件的源码
+  records: Option<Mutex<HashMap<PathBuf, SourceRecord<'a>>>>,
+  // 读取过的原始源码, 生成代码片段时不再重复读取
+  sources: Mutex<HashMap<PathBuf, &'a str>>,
 }
 
-impl TrackingProvider {
-  pub fn new() -> Self {
+impl<'a> TrackingProvider<'a> {
+  pub fn new(provider: &'a BoxedProvider, options: &CompileCssOptions) -> Self {
     TrackingProvider {
-      file_provider: FileProvider::new(),
+      provider,
       dependencies: Arc::new(Mutex::new(Vec::new())),
       imports: Arc::new(RwLock::new(HashMap::new())),
+      preprocess: options.preprocess.clone(),
+      preprocessed: SourceStore::default(),
+      externals: options.externals.clone(),
+      external_specifiers: Mutex::new(Vec::new()),
+      records: options.source_map.then(|| Mutex::new(HashMap::new())),
+      sources: Mutex::new(HashMap::new()),
     }
   }
+
+  fn is_external(&self, specifier: &str) -> bool {
+    REMOTE_URL_REGEX.is_match(specifier) || self.externals.iter().any(|r| r.is_match(specifier))
+  }
+
+  fn external_specifier(&self, index: usize) -> Option<String> {
+    self.external_specifiers.lock().unwrap().get(index).cloned()
+  }
+
+  // 读取时记录的未经预处理的源码, 用于生成代码片段
+  fn source(&self, path: &Path) -> Option<&'a str> {
+    self.sources.lock().unwrap().get(path).copied()
+  }
 }
 
-impl SourceProvider for TrackingProvider {
-  type Error = <FileProvider as SourceProvider>::Error;
+impl SourceProvider for TrackingProvider<'_> {
+  type Error = io::Error;
 
   fn read(&self, path: &Path) -> Result<&str, Self::Error> {
-    let result = self.file_provider.read(path)?;
+    // 外部引入读取为占位规则, 打包后留在 @import 的位置, 并带有对应的 @media / @supports / @layer
+    if let Some(index) = path.to_string_lossy().strip_prefix(EXTERNAL_PATH_PREFIX) {
+      return Ok(
+        self
+          .preprocessed
+          .push(format!("@{} {};", EXTERNAL_AT_RULE, index)),
+      );
+    }
+    let result = self.provider.read(path)?;
     self.dependencies.lock().unwrap().push(path.to_path_buf());
-    Ok(result)
+    self
+      .sources
+      .lock()
+      .unwrap()
+      .insert(path.to_path_buf(), result);
+    let preprocessed = self
+      .preprocess
+      .as_ref()
+      .map(|preprocess| preprocess(result));
+    let text = match &preprocessed {
+      Some(tracked) => self.preprocessed.push(tracked.text.clone()),
+      None => result,
+    };
+    if let Some(records) = &self.records {
+      records.lock().unwrap().insert(
+        path.to_path_buf(),
+        SourceRecord {
+          source: result,
+          preprocessed,
+        },
+      );
+    }
+    Ok(text)
   }
 
   fn resolve(&self, specifier: &str, originating_file: &Path) -> Result<PathBuf, Self::Error> {
-    let result: PathBuf = self.file_provider.resolve(specifier, originating_file)?;
+    // 每个外部引入使用不同的虚拟路径, 避免打包时合并多个 @import 的条件
+    if self.is_external(specifier) {
+      let mut specifiers = self.external_specifiers.lock().unwrap();
+      specifiers.push(specifier.to_string());
+      return Ok(PathBuf::from(format!(
+        "{}{}",
+        EXTERNAL_PATH_PREFIX,
+        specifiers.len() - 1
+      )));
+    }
+    let result: PathBuf = self.provider.resolve(specifier, originating_file)?;
     let specifier_path = result.to_path_buf();
     let originating_file_path = originating_file.to_path_buf();
 
@@ -55,29 +317,292 @@ impl SourceProvider for TrackingProvider {
   }
 }
 
-pub fn compile_css(entry: &Path) -> Result<CompileResult, Box<dyn Error>> {
-  let fs = TrackingProvider::new();
-  let mut bundler = Bundler::new(&fs, None, get_parser_options());
-  let stylesheet = bundler.bundle(entry).map_err(|e| {
-    let error: IoError = IoError::new(ErrorKind::Other, format!("BundleErrorKind: {}", e));
-    Box::new(error)
+pub fn compile_css(entry: &Path, options: &CompileCssOptions) -> Result<CompileResult, Diagnostic> {
+  let source_provider = source_provider(options);
+  let provider = TrackingProvider::new(&source_provider, options);
+  let warnings = Arc::new(RwLock::new(Vec::new()));
+  let parser_options = ParserOptions {
+    error_recovery: options.error_recovery,
+    warnings: options.error_recovery.then(|| warnings.clone()),
+    ..get_parser_options()
+  };
+  let mut bundler = Bundler::new(&provider, None, parser_options);
+  let mut stylesheet = bundler.bundle(entry).map_err(|e| {
+    let kind = match e.kind {
+      BundleErrorKind::ParserError(_) => DiagnosticKind::Parse,
+      BundleErrorKind::ResolverError(_) => DiagnosticKind::Resolve,
+      _ => DiagnosticKind::Bundle,
+    };
+    with_file_code_frame(&provider, Diagnostic::from_css_error(kind, &e)).context("BundleErrorKind")
   })?;
-  let result = stylesheet.to_css(get_printer_options())?;
+  let mut externals = Vec::new();
+  take_externals(&provider, &mut stylesheet.rules, &mut externals);
+  let fs: &dyn FileSystem = match &options.files {
+    Some(files) => files.as_ref(),
+    None => &OsFileSystem,
+  };
+  let root = entry.parent().unwrap_or(Path::new(""));
+  let sources = stylesheet.sources.clone();
+  let mut asset_visitor = AssetVisitor::new(&sources, &options.assets, root);
+  if let Some(limit) = options.inline_limit {
+    asset_visitor = asset_visitor.with_inline(InlineAssets { fs, limit });
+  }
+  stylesheet
+    .visit(&mut asset_visitor)
+    .map_err(|e| Diagnostic::new(DiagnosticKind::Visit, e).context("Asset error"))?;
+  let assets = asset_visitor.assets;
+  let asset_warnings = asset_visitor.warnings;
+  let mut source_map = options
+    .source_map
+    .then(|| SourceMap::new(&root.to_string_lossy()));
+  let result = stylesheet
+    .to_css(PrinterOptions {
+      source_map: source_map.as_mut(),
+      ..get_printer_options(options.targets)
+    })
+    .map_err(|e| {
+      with_file_code_frame(
+        &provider,
+        Diagnostic::from_css_error(DiagnosticKind::Serialize, &e),
+      )
+    })?;
 
-  let dependencies = fs.dependencies.lock().unwrap().clone();
-  let imports = fs.imports.read().unwrap().clone();
+  let mut dependencies = provider.dependencies.lock().unwrap().clone();
+  for path in assets.iter().filter_map(|asset| asset.path.as_ref()) {
+    if !dependencies.contains(path) && fs.is_file(path) {
+      dependencies.push(path.clone());
+    }
+  }
+  let imports = provider.imports.read().unwrap().clone();
+  let warnings = warnings
+    .read()
+    .unwrap()
+    .iter()
+    // 外部引入的占位规则是未知的 at-rule
+    .filter(|e| {
+      !e.loc
+        .as_ref()
+        .is_some_and(|loc| loc.filename.starts_with(EXTERNAL_PATH_PREFIX))
+    })
+    .map(|e| {
+      with_file_code_frame(
+        &provider,
+        Diagnostic::from_css_error(DiagnosticKind::Parse, e),
+      )
+      .warning()
+    })
+    .chain(
+      asset_warnings
+        .into_iter()
+        .map(|warning| with_file_code_frame(&provider, warning)),
+    )
+    .collect();
+
+  let css = externals
+    .iter()
+    .map(|(_, rule)| rule.as_str())
+    .chain([result.code.as_str()])
+    .collect::<String>();
+  let source_map = match source_map {
+    Some(map) => {
+      let offset = (css.len() - result.code.len()) as u32;
+      Some(
+        bundle_source_map(&provider, &map, &sources, root, offset).map_err(|e| {
+          Diagnostic::new(DiagnosticKind::Serialize, e.to_string()).context("Source map error")
+        })?,
+      )
+    }
+    None => None,
+  };
 
   Ok(CompileResult {
-    css: result.code,
+    css,
     dependencies,
     imports,
+    warnings,
+    externals: externals
+      .into_iter()
+      .map(|(specifier, _)| specifier)
+      .collect(),
+    assets,
+    source_map,
   })
 }
 
+/*
+ * 打包输出的 source map 按文件还原到预处理前的源码上
+ * @param offset: 顶部外部引入的长度, 打包的输出在同一行中后移
+ */
+fn bundle_source_map(
+  provider: &TrackingProvider,
+  map: &SourceMap,
+  sources: &[String],
+  root: &Path,
+  offset: u32,
+) -> Result<SourceMap, Box<dyn Error>> {
+  let mut result = SourceMap::new(&root.to_string_lossy());
+  let Some(records) = &provider.records else {
+    return Ok(result);
+  };
+  let records = records.lock().unwrap();
+  let mut indexes = HashMap::new();
+
+  for mapping in map.get_mappings() {
+    let Some(location) = mapping.original else {
+      continue;
+    };
+    // 外部引入的占位规则没有源码
+    let Some((path, record)) = sources
+      .get(location.source as usize)
+      .and_then(|path| Some((path, records.get(Path::new(path))?)))
+    else {
+      continue;
+    };
+    let source = match indexes.get(path) {
+      Some(index) => *index,
+      None => {
+        let index = result.add_source(path);
+        result.set_source_content(index as usize, record.source)?;
+        indexes.insert(path, index);
+        index
+      }
+    };
+    let (original_line, original_column) = match &record.preprocessed {
+      Some(tracked) => {
+        let position = position_to_offset(
+          &tracked.text,
+          location.original_line,
+          location.original_column,
+          true,
+        );
+        offset_to_position(record.source, tracked.unmap_offset(position))
+      }
+      None => (location.original_line, location.original_column),
+    };
+    let generated_column = match mapping.generated_line {
+      0 => mapping.generated_column + offset,
+      _ => mapping.generated_column,
+    };
+    result.add_mapping(
+      mapping.generated_line,
+      generated_column,
+      Some(OriginalLocation {
+        original_line,
+        original_column,
+        source,
+        name: None,
+      }),
+    );
+  }
+
+  Ok(result)
+}
+
+/*
+ * 按打包后的顺序取出外部引入的占位规则, 同一个地址只保留第一个
+ * 返回 (specifier, 压缩后的 @import 规则), 只移除占位规则和因此变空的 @media / @supports / @layer
+ */
+fn take_externals(
+  provider: &TrackingProvider,
+  rules: &mut CssRuleList,
+  externals: &mut Vec<(String, String)>,
+) {
+  rules.0.retain_mut(|rule| {
+    if let Some(import) = external_import(provider, rule) {
+      let specifier = import.url.to_string();
+      if !externals.iter().any(|(s, _)| s == &specifier) {
+        let options = PrinterOptions {
+          minify: true,
+          ..PrinterOptions::default()
+        };
+        let rule = import.to_css_string(options).unwrap_or_default();
+        externals.push((specifier, rule));
+      }
+      return false;
+    }
+    let children = match rule {
+      CssRule::Media(media) => &mut media.rules,
+      CssRule::Supports(supports) => &mut supports.rules,
+      CssRule::LayerBlock(layer) => &mut layer.rules,
+      _ => return true,
+    };
+    let len = children.0.len();
+    take_externals(provider, children, externals);
+    children.0.len() == len || !children.0.is_empty()
+  });
+}
+
+/*
+ * 打包时外部引入的规则依次包裹在 @layer, @media, @supports 中, 条件已经与父级合并
+ * @supports { @media { @layer { @<占位> n; } } } => @import "<specifier>" layer supports() media;
+ */
+fn external_import<'i>(provider: &TrackingProvider, rule: &CssRule<'i>) -> Option<ImportRule<'i>> {
+  let mut import = ImportRule {
+    url: "".into(),
+    layer: None,
+    supports: None,
+    media: MediaList::new(),
+    loc: Location {
+      source_index: 0,
+      line: 0,
+      column: 0,
+    },
+  };
+  let mut rule = rule;
+  if let CssRule::Supports(supports) = rule {
+    import.supports = Some(supports.condition.clone());
+    rule = single_rule(&supports.rules)?;
+  }
+  if let CssRule::Media(media) = rule {
+    import.media = media.query.clone();
+    rule = single_rule(&media.rules)?;
+  }
+  if let CssRule::LayerBlock(layer) = rule {
+    import.layer = Some(layer.name.clone());
+    rule = single_rule(&layer.rules)?;
+  }
+  let CssRule::Unknown(unknown) = rule else {
+    return None;
+  };
+  if unknown.name.as_ref() != EXTERNAL_AT_RULE {
+    return None;
+  }
+  let index = unknown.prelude.0.iter().find_map(|token| match token {
+    TokenOrValue::Token(Token::Number {
+      int_value: Some(index),
+      ..
+    }) => Some(*index as usize),
+    _ => None,
+  })?;
+  import.url = provider.external_specifier(index)?.into();
+  Some(import)
+}
+
+fn single_rule<'a, 'i>(rules: &'a CssRuleList<'i>) -> Option<&'a CssRule<'i>> {
+  match rules.0.as_slice() {
+    [rule] => Some(rule),
+    _ => None,
+  }
+}
+
+// 错误发生在被引入的文件中时, 使用该文件的源码生成代码片段
+fn with_file_code_frame(provider: &TrackingProvider, diagnostic: Diagnostic) -> Diagnostic {
+  match diagnostic
+    .file
+    .as_ref()
+    .and_then(|file| provider.source(Path::new(file)))
+  {
+    Some(source) => diagnostic.with_code_frame(source),
+    None => diagnostic,
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::assets::RewriteUrl;
   use crate::node_path::get_basename;
+  use crate::options::resolve_targets;
   use indoc::indoc;
   use insta::assert_snapshot;
   use std::fs;
@@ -123,16 +648,17 @@ mod tests {
     )
     .unwrap();
 
-    let return_compile_result: CompileResult = compile_css(css_path.as_path()).unwrap();
+    let return_compile_result: CompileResult =
+      compile_css(css_path.as_path(), &CompileCssOptions::default()).unwrap();
     let return_dependencies = return_compile_result.dependencies;
 
     assert_snapshot!(return_compile_result.css);
-    assert_eq!(return_dependencies.len(), 3);
+    assert_eq!(return_dependencies.len(), 4);
     let dependencies_names: Vec<String> = return_dependencies
       .iter()
       .map(|path| get_basename(path, true).unwrap())
       .collect();
-    assert_eq!(dependencies_names, vec!["a.css", "b.css", "c.css"]);
+    assert_eq!(dependencies_names, vec!["a.css", "b.css", "c.css", "b.png"]);
 
     let return_imports = return_compile_result.imports;
 
@@ -150,6 +676,397 @@ mod tests {
 
       assert_snapshot!(format!("{}: {:?}", key_str, values_str));
     }
+
+    // 1x1 的 png 为 69 字节, 等于 limit 时不内联
+    let inline = |limit: usize| {
+      let options = CompileCssOptions {
+        inline_limit: Some(limit),
+        ..CompileCssOptions::default()
+      };
+      compile_css(css_path.as_path(), &options).unwrap()
+    };
+    let result = inline(1024);
+    assert_snapshot!(result.css);
+    assert_eq!(result.dependencies.len(), 4);
+    assert_eq!(
+      inline(69).css,
+      ".c{padding:0}.b{padding:0;background:url(./b.png)}.a{color:red}.a .a-child{color:#00f}"
+    );
+  }
+
+  #[test]
+  fn test_bundle_resolver() {
+    let dir = tempdir().unwrap();
+    let src = dir.path().join("src");
+    let base = dir.path().join("node_modules/@ui/base");
+    fs::create_dir_all(src.join("styles")).unwrap();
+    fs::create_dir_all(&base).unwrap();
+    let css_path = src.join("a.css");
+    fs::write(
+      &css_path,
+      indoc! { r#"
+      @import "~@ui/base";
+      @import "@/styles/vars";
+      .a { color: red; }
+    "#},
+    )
+    .unwrap();
+    fs::write(src.join("styles/vars.css"), ".vars { color: blue; }").unwrap();
+    fs::write(base.join("package.json"), r#"{ "style": "base.css" }"#).unwrap();
+    fs::write(base.join("base.css"), ".base { margin: 0; }").unwrap();
+
+    let options = CompileCssOptions {
+      resolver: Resolver {
+        alias: vec![("@".to_string(), src.clone())],
+        ..Resolver::default()
+      },
+      ..CompileCssOptions::default()
+    };
+    let result = compile_css(css_path.as_path(), &options).unwrap();
+    assert_snapshot!(result.css);
+
+    let imports = result.imports.get(&css_path).unwrap();
+    assert_eq!(
+      imports,
+      &vec![base.join("base.css"), src.join("styles/vars.css")]
+    );
+
+    let error = compile_css(css_path.as_path(), &CompileCssOptions::default()).unwrap_err();
+    assert_eq!(error.kind, DiagnosticKind::Resolve);
+  }
+
+  #[test]
+  fn test_bundle_memory_files() {
+    let dir = tempdir().unwrap();
+    let css_path = dir.path().join("a.css");
+    fs::write(dir.path().join("c.css"), ".c { padding: 0 }").unwrap();
+    fs::write(dir.path().join("b.css"), ".b { color: red }").unwrap();
+    let files = [
+      (
+        css_path.clone(),
+        "@import './b.css';\n.a { color: red; }".to_string(),
+      ),
+      (
+        dir.path().join("b.css"),
+        "@import './c.css';\n.b { color: blue; }".to_string(),
+      ),
+      (
+        dir.path().join("d.css"),
+        ".d { color: red; }\n.e color: red;".to_string(),
+      ),
+    ];
+
+    let options = CompileCssOptions {
+      files: Some(Arc::new(MemoryFiles::new(files.clone(), true))),
+      ..CompileCssOptions::default()
+    };
+    let result = compile_css(css_path.as_path(), &options).unwrap();
+    assert_snapshot!(result.css);
+    let dependencies_names: Vec<String> = result
+      .dependencies
+      .iter()
+      .map(|path| get_basename(path, true).unwrap())
+      .collect();
+    assert_eq!(dependencies_names, vec!["a.css", "b.css", "c.css"]);
+    assert_eq!(
+      result.imports.get(&css_path).unwrap(),
+      &vec![dir.path().join("b.css")]
+    );
+
+    // 不从磁盘读取时 c.css 不存在
+    let options = CompileCssOptions {
+      files: Some(Arc::new(MemoryFiles::new(files.clone(), false))),
+      ..CompileCssOptions::default()
+    };
+    assert!(compile_css(css_path.as_path(), &options).is_err());
+
+    // 代码片段来自内存中的源码
+    let diagnostic = compile_css(&dir.path().join("d.css"), &options).unwrap_err();
+    assert_eq!(diagnostic.line, Some(2));
+    assert!(diagnostic.code_frame.unwrap().contains(".e color: red;"));
+  }
+
+  #[test]
+  fn test_bundle_hooks() {
+    let dir = tempdir().unwrap();
+    let css_path = dir.path().join("a.css");
+    fs::write(
+      &css_path,
+      "@import 'virtual:theme';\n@import './b.css';\n.a { color: red; }",
+    )
+    .unwrap();
+    fs::write(dir.path().join("b.css"), ".b { color: red; }").unwrap();
+
+    let theme = dir.path().join("theme.css");
+    let options = CompileCssOptions {
+      hooks: SourceHooks {
+        read: Some({
+          let theme = theme.clone();
+          Arc::new(move |path: &Path| {
+            Ok((path == theme).then(|| ".theme { color: blue; }".to_string()))
+          })
+        }),
+        resolve: Some({
+          let theme = theme.clone();
+          Arc::new(move |specifier: &str, _: &Path| match specifier {
+            "virtual:theme" => Ok(Some(theme.clone())),
+            "virtual:missing" => Err("Cannot find virtual:missing".to_string()),
+            _ => Ok(None),
+          })
+        }),
+      },
+      ..CompileCssOptions::default()
+    };
+    let result = compile_css(css_path.as_path(), &options).unwrap();
+    assert_snapshot!(result.css);
+    assert_eq!(
+      result.imports.get(&css_path).unwrap(),
+      &vec![theme.clone(), dir.path().join("b.css")]
+    );
+
+    fs::write(&css_path, "@import 'virtual:missing';").unwrap();
+    let diagnostic = compile_css(css_path.as_path(), &options).unwrap_err();
+    assert_eq!(diagnostic.kind, DiagnosticKind::Resolve);
+    assert!(diagnostic.message.contains("Cannot find virtual:missing"));
+  }
+
+  #[test]
+  // 代码片段使用读取时记录的源码, 不再调用 read hook
+  fn test_bundle_code_frame_reads() {
+    let dir = tempdir().unwrap();
+    let css_path = dir.path().join("a.css");
+    fs::write(&css_path, "@import './b.css';\n.a { color: red; }").unwrap();
+    fs::write(
+      dir.path().join("b.css"),
+      ".b { color: red; }\n.c color: red;",
+    )
+    .unwrap();
+
+    let reads = Arc::new(Mutex::new(Vec::new()));
+    let read: ReadHook = {
+      let reads = reads.clone();
+      Arc::new(move |path: &Path| {
+        reads
+          .lock()
+          .unwrap()
+          .push(get_basename(path, true).unwrap());
+        Ok(None)
+      })
+    };
+    let options = CompileCssOptions {
+      hooks: SourceHooks {
+        read: Some(read),
+        resolve: None,
+      },
+      ..CompileCssOptions::default()
+    };
+    let diagnostic = compile_css(css_path.as_path(), &options).unwrap_err();
+    assert_eq!(diagnostic.line, Some(2));
+    assert!(diagnostic.code_frame.unwrap().contains(".c color: red;"));
+    assert_eq!(*reads.lock().unwrap(), vec!["a.css", "b.css"]);
+  }
+
+  #[test]
+  fn test_bundle_targets() {
+    let dir = tempdir().unwrap();
+    let css_path = dir.path().join("a.css");
+    fs::write(&css_path, ".a { color: red; .a-child { color: blue; } }").unwrap();
+
+    let options = CompileCssOptions {
+      targets: resolve_targets(Some(&["chrome >= 120".to_string()]), None).unwrap(),
+      ..CompileCssOptions::default()
+    };
+    let result = compile_css(css_path.as_path(), &options).unwrap();
+
+    assert_snapshot!(result.css);
+  }
+
+  #[test]
+  fn test_bundle_externals() {
+    let dir = tempdir().unwrap();
+    let css_path = dir.path().join("a.css");
+    fs::write(
+      &css_path,
+      indoc! { r#"
+      @import "./b.css";
+      @import "https://example.com/a.css" screen;
+      @import url(//cdn.example.com/theme.css) supports(display: grid);
+      .a { color: red; }
+    "#},
+    )
+    .unwrap();
+    fs::write(
+      dir.path().join("b.css"),
+      indoc! { r#"
+      @import "https://example.com/a.css";
+      @import "@platform/base.css";
+      .b { color: blue; }
+    "#},
+    )
+    .unwrap();
+
+    let options = CompileCssOptions {
+      externals: vec![Regex::new("^@platform/").unwrap()],
+      ..CompileCssOptions::default()
+    };
+    let result = compile_css(css_path.as_path(), &options).unwrap();
+    assert_snapshot!(result.css);
+    assert_eq!(
+      result.externals,
+      vec![
+        "https://example.com/a.css",
+        "@platform/base.css",
+        "//cdn.example.com/theme.css"
+      ]
+    );
+    let dependencies_names: Vec<String> = result
+      .dependencies
+      .iter()
+      .map(|path| get_basename(path, true).unwrap())
+      .collect();
+    assert_eq!(dependencies_names, vec!["a.css", "b.css"]);
+    assert_eq!(
+      result.imports.get(&css_path).unwrap(),
+      &vec![dir.path().join("b.css")]
+    );
+  }
+
+  #[test]
+  fn test_bundle_externals_nested() {
+    let dir = tempdir().unwrap();
+    let css_path = dir.path().join("a.css");
+    fs::write(
+      &css_path,
+      indoc! { r#"
+      @import "./b.css" screen;
+      @media print {}
+      @supports (display: grid) {}
+      .a { color: red; }
+    "#},
+    )
+    .unwrap();
+    fs::write(
+      dir.path().join("b.css"),
+      indoc! { r#"
+      @import "https://example.com/b.css" layer(base) supports(display: flex);
+      .b { color: blue; }
+    "#},
+    )
+    .unwrap();
+
+    // 每个文件只读取一次
+    let reads = Arc::new(Mutex::new(Vec::new()));
+    let read: ReadHook = {
+      let reads = reads.clone();
+      Arc::new(move |path: &Path| {
+        reads
+          .lock()
+          .unwrap()
+          .push(get_basename(path, true).unwrap());
+        Ok(None)
+      })
+    };
+    let options = CompileCssOptions {
+      hooks: SourceHooks {
+        read: Some(read),
+        resolve: None,
+      },
+      error_recovery: true,
+      ..CompileCssOptions::default()
+    };
+    let result = compile_css(css_path.as_path(), &options).unwrap();
+    assert!(result.warnings.is_empty());
+    // 用户写的空规则保留, 只移除外部引入留下的 @media / @supports / @layer
+    assert_snapshot!(result.css);
+    assert_eq!(result.externals, vec!["https://example.com/b.css"]);
+    let mut reads = reads.lock().unwrap().clone();
+    reads.sort();
+    assert_eq!(reads, vec!["a.css", "b.css"]);
+  }
+
+  #[test]
+  fn test_bundle_assets() {
+    let dir = tempdir().unwrap();
+    let css_path = dir.path().join("a.css");
+    fs::write(
+      &css_path,
+      indoc! { r#"
+      @import "./styles/b.css";
+      .a { background: url(./a.png?v=1); }
+    "#},
+    )
+    .unwrap();
+    fs::create_dir(dir.path().join("styles")).unwrap();
+    fs::write(
+      dir.path().join("styles/b.css"),
+      indoc! { r#"
+      .b {
+        background: url("../images/b.png"), url(data:image/png;base64,AAAA);
+        mask: url(https://cdn.example.com/mask.svg);
+      }
+    "#},
+    )
+    .unwrap();
+    fs::write(dir.path().join("a.png"), "").unwrap();
+
+    let rewrite: RewriteUrl =
+      Arc::new(|asset: &Asset| Ok(asset.url.ends_with(".svg").then(|| "/mask.svg".to_string())));
+    let options = CompileCssOptions {
+      assets: AssetOptions {
+        public_path: Some("https://cdn.com/".to_string()),
+        rewrite: Some(rewrite),
+      },
+      ..CompileCssOptions::default()
+    };
+    let result = compile_css(css_path.as_path(), &options).unwrap();
+    assert_snapshot!(result.css);
+
+    let assets = result
+      .assets
+      .iter()
+      .map(|asset| {
+        (
+          asset.url.as_str(),
+          get_basename(&asset.file, true).unwrap(),
+          asset.line,
+          asset
+            .path
+            .as_ref()
+            .map(|path| get_basename(path, true).unwrap()),
+        )
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(
+      assets,
+      vec![
+        (
+          "../images/b.png",
+          "b.css".to_string(),
+          2,
+          Some("b.png".to_string())
+        ),
+        ("data:image/png;base64,AAAA", "b.css".to_string(), 2, None),
+        (
+          "https://cdn.example.com/mask.svg",
+          "b.css".to_string(),
+          3,
+          None
+        ),
+        (
+          "./a.png?v=1",
+          "a.css".to_string(),
+          2,
+          Some("a.png".to_string())
+        ),
+      ]
+    );
+    // 只有存在的文件加入 dependencies
+    let dependencies_names: Vec<String> = result
+      .dependencies
+      .iter()
+      .map(|path| get_basename(path, true).unwrap())
+      .collect();
+    assert_eq!(dependencies_names, vec!["a.css", "b.css", "a.png"]);
   }
 
   #[test]
@@ -159,13 +1076,13 @@ mod tests {
     fs::write(
       &css_path,
       indoc! { r#"
-      @import "https://example.com/b.css";
+      @import "~missing/b.css";
         .a { color: red; .a-child { color: blue; } }
       "# },
     )
     .unwrap();
 
-    let result = compile_css(css_path.as_path());
+    let result = compile_css(css_path.as_path(), &CompileCssOptions::default());
 
     assert!(result.is_err());
   }
@@ -182,8 +1099,53 @@ mod tests {
     )
     .unwrap();
 
-    let result = compile_css(css_path.as_path());
+    let result = compile_css(css_path.as_path(), &CompileCssOptions::default());
 
     assert!(result.is_err());
   }
+
+  #[test]
+  fn test_import_parse_err_diagnostic() {
+    let dir = tempdir().unwrap();
+    let css_path = dir.path().join("a.css");
+    fs::write(&css_path, "@import './b.css';\n.a { color: red; }").unwrap();
+    fs::write(
+      dir.path().join("b.css"),
+      ".b { color: red; }\n.c { color: red; }\n@import './c.css';\n",
+    )
+    .unwrap();
+
+    let diagnostic = compile_css(css_path.as_path(), &CompileCssOptions::default()).unwrap_err();
+
+    assert_eq!(diagnostic.kind, DiagnosticKind::Parse);
+    let file = diagnostic.file.as_ref().unwrap();
+    assert_eq!(get_basename(file, true).unwrap(), "b.css");
+    assert_eq!(diagnostic.line, Some(3));
+    assert_snapshot!(diagnostic.code_frame.unwrap());
+  }
+
+  #[test]
+  fn test_error_recovery_warnings() {
+    let dir = tempdir().unwrap();
+    let css_path = dir.path().join("a.css");
+    fs::write(&css_path, "@import './b.css';\n.a { color: red; }").unwrap();
+    fs::write(
+      dir.path().join("b.css"),
+      ".b { color: red; }\n.c { width 1px; }\n",
+    )
+    .unwrap();
+
+    let options = CompileCssOptions {
+      error_recovery: true,
+      ..CompileCssOptions::default()
+    };
+    let result = compile_css(css_path.as_path(), &options).unwrap();
+    assert_snapshot!(result.css);
+    assert_eq!(result.warnings.len(), 1);
+    let warning = &result.warnings[0];
+    let file = warning.file.as_ref().unwrap();
+    assert_eq!(get_basename(file, true).unwrap(), "b.css");
+    assert_eq!(warning.line, Some(2));
+    assert_snapshot!(warning.code_frame.as_ref().unwrap());
+  }
 }
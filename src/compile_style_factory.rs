@@ -0,0 +1,426 @@
+use crate::assets::AssetOptions;
+use crate::compile_css::{compile_css, CompileCssOptions};
+use crate::convert_css::protect_media_units;
+use crate::diagnostic::{Diagnostic, DiagnosticKind};
+use crate::js_asset::JSAsset;
+use crate::js_compile_css::{with_hooks, JSCompileCssOptions, JSCompileResult};
+use crate::js_diagnostic::{to_napi_error, JSDiagnostic};
+use crate::js_source_hooks::{with_js_hooks, JsHookRefs};
+use crate::segment::Placeholders;
+use crate::source_map::{position_to_offset, TrackedText};
+use crate::style_factory::{
+  build_style_factory, invalid_option, FactoryOptions, StyleFactoryOptions,
+};
+use napi::bindgen_prelude::AsyncTask;
+use napi::{Env, Task};
+use once_cell::sync::OnceCell;
+use parcel_sourcemap::{OriginalLocation, SourceMap};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/*
+ * 打包后的 styleFactory 模块, @import 已经内联, 不再生成 import 语句
+ * dependencies / imports 与 compileCSS 一致, 用于 watch
+ */
+#[napi(object)]
+#[derive(Debug)]
+pub struct CompileStyleFactoryResult {
+  pub code: String,
+  pub map: Option<String>,
+  pub warnings: Vec<JSDiagnostic>,
+  pub dts: Option<String>,
+  pub class_names: Option<Vec<String>>,
+  pub css: Option<String>,
+  pub dependencies: Vec<String>,
+  pub imports: HashMap<String, Vec<String>>,
+  pub externals: Vec<String>,
+  pub assets: Vec<JSAsset>,
+}
+
+/*
+ * 先打包再生成 styleFactory 模块
+ * @param compile_options: 打包的选项, preprocess 和 source_map 由 styleFactory 的选项决定
+ */
+pub fn compile_style_factory(
+  entry: &Path,
+  options: &FactoryOptions,
+  compile_options: CompileCssOptions,
+) -> Result<CompileStyleFactoryResult, Diagnostic> {
+  // lightningcss 打包时无法解析 media query 中的 rpx, 读取文件后先替换成占位符
+  // 占位符的 nonce 由入口的源码计算, Bundler 读取入口之后才会解析其中的 @import
+  let placeholders = Arc::new(OnceCell::new());
+  let preprocess = {
+    let placeholders = placeholders.clone();
+    let units = options.convert.units.clone();
+    Arc::new(move |css: &str| {
+      protect_media_units(
+        css,
+        placeholders.get_or_init(|| Placeholders::new(css)),
+        &units,
+      )
+    })
+  };
+  let mut compiled = compile_css(
+    entry,
+    &CompileCssOptions {
+      preprocess: Some(preprocess),
+      source_map: options.convert.source_map,
+      ..compile_options
+    },
+  )?;
+  let restored = match placeholders.get() {
+    Some(placeholders) => placeholders.restore_media_units(&compiled.css),
+    None => TrackedText::new(compiled.css.as_str()),
+  };
+
+  let mut factory_options = options.clone();
+  if factory_options.convert.filename.is_empty() {
+    factory_options.convert.filename = entry.to_string_lossy().to_string();
+  }
+  // url() 按所在的文件解析, 已经在打包时收集和改写
+  factory_options.convert.assets = AssetOptions::default();
+  let mut result = build_style_factory(restored.text.clone(), &factory_options)?;
+  if let (Some(map), Some(bundle_map)) = (&result.map, &mut compiled.source_map) {
+    result.map = Some(
+      bundle_factory_map(map, &restored, &compiled.css, bundle_map).map_err(|e| {
+        Diagnostic::new(DiagnosticKind::Serialize, e.to_string()).context("Source map error")
+      })?,
+    );
+  }
+  let compiled = JSCompileResult::from(compiled);
+
+  Ok(CompileStyleFactoryResult {
+    code: result.code,
+    map: result.map,
+    warnings: compiled
+      .warnings
+      .into_iter()
+      .chain(result.warnings)
+      .collect(),
+    dts: result.dts,
+    class_names: result.class_names,
+    css: result.css,
+    dependencies: compiled.dependencies,
+    imports: compiled.imports,
+    externals: compiled.externals,
+    assets: compiled.assets,
+  })
+}
+
+/*
+ * styleFactory 的 source map 指向打包后的 css, 再经过打包的 source map 映射到各个源文件
+ * @param restored: 还原 media query 单位后的 css, 可以换算回打包输出中的位置
+ */
+fn bundle_factory_map(
+  map: &str,
+  restored: &TrackedText,
+  compiled_css: &str,
+  bundle_map: &mut SourceMap,
+) -> Result<String, Box<dyn Error>> {
+  let factory_map = SourceMap::from_json("/", map)?;
+  let mut result = SourceMap::new("/");
+  for mapping in factory_map.get_mappings() {
+    let Some(location) = mapping.original else {
+      continue;
+    };
+    let offset = restored.unmap_offset(position_to_offset(
+      &restored.text,
+      location.original_line,
+      location.original_column,
+      true,
+    ));
+    // 打包的 source map 中的列以字节为单位
+    let before = &compiled_css[..offset.min(compiled_css.len())];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    let line = before.matches('\n').count() as u32;
+    let Some(original) = bundle_map
+      .find_closest_mapping(line, (before.len() - line_start) as u32)
+      .and_then(|mapping| mapping.original)
+    else {
+      continue;
+    };
+    let source = result.add_source(bundle_map.get_source(original.source)?);
+    if let Ok(content) = bundle_map.get_source_content(original.source) {
+      result.set_source_content(source as usize, content)?;
+    }
+    result.add_mapping(
+      mapping.generated_line,
+      mapping.generated_column,
+      Some(OriginalLocation { source, ..original }),
+    );
+  }
+  Ok(result.to_json(None)?)
+}
+
+// 打包的选项, 未设置的 targets / errorRecovery / publicPath 与 styleFactory 的选项一致
+fn to_bundle_options(
+  options: &FactoryOptions,
+  compile_options: &JSCompileCssOptions,
+) -> Result<CompileCssOptions, napi::Error> {
+  let mut result = compile_options.to_compile_options()?;
+  if compile_options.targets.is_none() {
+    result.targets = options.convert.targets;
+  }
+  result.error_recovery = compile_options
+    .error_recovery
+    .unwrap_or(options.convert.error_recovery);
+  if compile_options.public_path.is_none() {
+    result.assets.public_path = options.convert.assets.public_path.clone();
+  }
+  Ok(result)
+}
+
+#[napi(js_name = "compileStyleFactory")]
+pub fn js_compile_style_factory(
+  env: Env,
+  entry: String,
+  options: Option<StyleFactoryOptions>,
+  compile_options: Option<JSCompileCssOptions>,
+) -> Result<CompileStyleFactoryResult, napi::Error> {
+  let factory_options = options
+    .unwrap_or_default()
+    .to_factory_options()
+    .map_err(|e| to_napi_error(env, &invalid_option(e)))?;
+  let compile_options = compile_options.unwrap_or_default();
+  let bundle_options = to_bundle_options(&factory_options, &compile_options)?;
+
+  let entry = Path::new(&entry);
+  let result = match compile_options.hook_functions() {
+    Some(functions) => with_js_hooks(env, functions, |hooks| {
+      compile_style_factory(entry, &factory_options, with_hooks(bundle_options, hooks))
+    }),
+    None => compile_style_factory(entry, &factory_options, bundle_options),
+  };
+  result.map_err(|e| to_napi_error(env, &e.context("Error")))
+}
+
+pub struct CompileStyleFactoryTask {
+  entry: PathBuf,
+  options: FactoryOptions,
+  compile_options: CompileCssOptions,
+  diagnostic: Option<Diagnostic>,
+  hook_refs: Option<JsHookRefs>,
+}
+
+impl Task for CompileStyleFactoryTask {
+  type Output = CompileStyleFactoryResult;
+  type JsValue = CompileStyleFactoryResult;
+
+  fn compute(&mut self) -> napi::Result<Self::Output> {
+    compile_style_factory(&self.entry, &self.options, self.compile_options.clone()).map_err(|e| {
+      let e = e.context("Error");
+      let error = napi::Error::from_reason(e.to_string());
+      self.diagnostic = Some(e);
+      error
+    })
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+    Ok(output)
+  }
+
+  fn reject(&mut self, env: Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+    match self.diagnostic.take() {
+      Some(diagnostic) => Err(to_napi_error(env, &diagnostic)),
+      None => Err(err),
+    }
+  }
+
+  fn finally(&mut self, env: Env) -> napi::Result<()> {
+    match self.hook_refs.take() {
+      Some(hook_refs) => hook_refs.release(env),
+      None => Ok(()),
+    }
+  }
+}
+
+#[napi(js_name = "compileStyleFactoryAsync")]
+pub fn js_compile_style_factory_async(
+  env: Env,
+  entry: String,
+  options: Option<StyleFactoryOptions>,
+  compile_options: Option<JSCompileCssOptions>,
+) -> Result<AsyncTask<CompileStyleFactoryTask>, napi::Error> {
+  let factory_options = options
+    .unwrap_or_default()
+    .to_factory_options()
+    .map_err(|e| to_napi_error(env, &invalid_option(e)))?;
+  let compile_options = compile_options.unwrap_or_default();
+  let mut bundle_options = to_bundle_options(&factory_options, &compile_options)?;
+  let hook_refs = match compile_options.hook_functions() {
+    Some(functions) => {
+      let (hook_refs, hooks) = JsHookRefs::new(env, functions)?;
+      bundle_options = with_hooks(bundle_options, hooks);
+      Some(hook_refs)
+    }
+    None => None,
+  };
+  Ok(AsyncTask::new(CompileStyleFactoryTask {
+    entry: PathBuf::from(entry),
+    options: factory_options,
+    compile_options: bundle_options,
+    diagnostic: None,
+    hook_refs,
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::convert_css::ConvertCssOptions;
+  use crate::memory_provider::MemoryFiles;
+  use crate::node_path::get_basename;
+  use crate::resolver::Resolver;
+  use indoc::indoc;
+  use insta::assert_snapshot;
+  use std::fs;
+  use tempfile::tempdir;
+
+  #[test]
+  fn test_compile_style_factory() {
+    let dir = tempdir().unwrap();
+    let entry = dir.path().join("a.css");
+    fs::write(
+      &entry,
+      indoc! {r#"
+        @import "./b.css";
+        .a { width: 100rpx; }
+      "#},
+    )
+    .unwrap();
+    fs::write(
+      dir.path().join("b.css"),
+      indoc! {r#"
+        @media (max-width: 600rpx) { .b { height: 20rpx; } }
+        :host { color: red; }
+      "#},
+    )
+    .unwrap();
+
+    let result = compile_style_factory(
+      &entry,
+      &FactoryOptions::default(),
+      CompileCssOptions::default(),
+    )
+    .unwrap();
+    assert!(!result.code.contains("import"));
+    assert_snapshot!(result.code);
+
+    let dependencies = result
+      .dependencies
+      .iter()
+      .filter_map(|d| get_basename(d, true))
+      .collect::<Vec<_>>();
+    assert_eq!(dependencies, vec!["a.css", "b.css"]);
+    assert_eq!(result.imports.len(), 1);
+
+    let mut task = CompileStyleFactoryTask {
+      entry: dir.path().join("c.css"),
+      options: FactoryOptions::default(),
+      compile_options: CompileCssOptions::default(),
+      diagnostic: None,
+      hook_refs: None,
+    };
+    let error = task.compute().unwrap_err();
+    assert_eq!(error.reason, task.diagnostic.unwrap().to_string());
+  }
+
+  #[test]
+  // 占位符的 nonce 与入口的路径无关, 源码中的同名文本不会被还原
+  fn test_compile_style_factory_placeholder_nonce() {
+    let dir = tempdir().unwrap();
+    let entry = dir.path().join("a.css");
+    let path_placeholder = Placeholders::new(&entry.to_string_lossy()).media_unit("rpx", "5");
+    let source = format!(".a {{ --x: {}; }}", path_placeholder);
+    fs::write(&entry, &source).unwrap();
+
+    let options = FactoryOptions::default();
+    let result = compile_style_factory(&entry, &options, CompileCssOptions::default()).unwrap();
+    assert!(result.code.contains(&path_placeholder));
+  }
+
+  #[test]
+  fn test_compile_style_factory_options() {
+    let files = MemoryFiles::new(
+      [
+        (
+          PathBuf::from("/src/a.css"),
+          "@import '@/b.css';\n.a { color: red; }".to_string(),
+        ),
+        (
+          PathBuf::from("/lib/b.css"),
+          ".b { width: 10rpx; }".to_string(),
+        ),
+      ],
+      false,
+    );
+    let compile_options = CompileCssOptions {
+      resolver: Resolver {
+        alias: vec![("@".to_string(), PathBuf::from("/lib"))],
+        ..Resolver::default()
+      },
+      files: Some(Arc::new(files)),
+      ..CompileCssOptions::default()
+    };
+    let result = compile_style_factory(
+      Path::new("/src/a.css"),
+      &FactoryOptions::default(),
+      compile_options,
+    )
+    .unwrap();
+    assert!(result.code.contains(r#""b{width:" , rpx(10)"#));
+    assert_eq!(result.dependencies, vec!["/src/a.css", "/lib/b.css"]);
+  }
+
+  #[test]
+  // source map 指向各个源文件, 位置还原到替换 media query 单位之前
+  fn test_compile_style_factory_source_map() {
+    let dir = tempdir().unwrap();
+    let entry = dir.path().join("a.css");
+    fs::write(&entry, "@import './b.css';\n.a { color: red; }\n").unwrap();
+    fs::write(
+      dir.path().join("b.css"),
+      "/* b */\n@media (max-width: 600rpx) { .b { height: 20rpx; } }\n",
+    )
+    .unwrap();
+
+    let options = FactoryOptions {
+      convert: ConvertCssOptions {
+        source_map: true,
+        ..ConvertCssOptions::default()
+      },
+      ..FactoryOptions::default()
+    };
+    let result = compile_style_factory(&entry, &options, CompileCssOptions::default()).unwrap();
+    let mut map = SourceMap::from_json("/", &result.map.unwrap()).unwrap();
+
+    let mut original = |pattern: &str| {
+      let (line, line_text) = result
+        .code
+        .lines()
+        .enumerate()
+        .find(|(_, line)| line.contains("var css"))
+        .unwrap();
+      let column = line_text.find(pattern).unwrap();
+      let original = map
+        .find_closest_mapping(line as u32, column as u32)
+        .and_then(|mapping| mapping.original)
+        .unwrap();
+      (
+        map.get_source(original.source).unwrap().to_string(),
+        original.original_line,
+        original.original_column,
+      )
+    };
+    assert_eq!(
+      original(r#"." , prefix , "b{height"#),
+      ("b.css".to_string(), 1, 29)
+    );
+    assert_eq!(
+      original(r#"." , prefix , "a{color"#),
+      ("a.css".to_string(), 1, 0)
+    );
+  }
+}
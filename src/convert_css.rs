@@ -2,50 +2,110 @@ use lightningcss::{
   properties::custom::{Token, TokenList, TokenOrValue},
   rules::{unknown::UnknownAtRule, CssRule},
   selector::{Component, Selector, SelectorList},
-  stylesheet::StyleSheet,
-  traits::ToCss,
-  values::{ident::Ident, string::CSSString},
+  stylesheet::{ParserOptions, PrinterOptions, StyleSheet},
+  targets::Targets,
+  traits::{Parse, ToCss},
+  values::{ident::Ident, length::LengthValue, string::CSSString, url::Url},
   visitor::{Visit, VisitTypes, Visitor},
 };
 
-use crate::options::{get_minify_options, get_parser_options, get_printer_options};
+use crate::assets::{Asset, AssetOptions};
+use crate::css_to_code::md5_hash;
+use crate::diagnostic::{Diagnostic, DiagnosticKind};
+use crate::options::{get_minify_options, get_parser_options, get_printer_options, get_targets};
+use crate::segment::{CssSegments, Placeholders, ScopedClass, Segment};
+use crate::source_map::{offset_to_position, position_to_offset, TrackedText};
+use cssparser::{ParseError as CssParseError, Parser, ParserInput, Token as CssToken};
+use lazy_regex::{lazy_regex, Captures, Regex};
+use once_cell::sync::Lazy;
 use parcel_selectors::{
   attr::{AttrSelectorOperator, ParsedCaseSensitivity},
   parser::LocalName,
 };
+use parcel_sourcemap::{OriginalLocation, SourceMap};
 use smallvec::SmallVec;
+use std::collections::HashMap;
 use std::error::Error;
+use std::fmt::Display;
+use std::ops::Range;
+use std::path::Path;
 use std::string::String;
+use std::sync::{Arc, RwLock};
 
 // Define constants for magic strings
-const PREFIX: &str = "__PREFIX__";
-const HOST: &str = "__HOST__";
-const RPX_FUNC: &str = "__RPX__";
-const IMPORT_STYLE: &str = "import-style";
 const META_TAG: &str = "meta:tag";
-const UNSUPPORTED_STAR: &str = "unsupported-star";
-const UNSUPPORTED_WEB_VIEW: &str = "unsupported-web-view";
 
-struct FactoryVisitor {
+struct FactoryVisitor<'o> {
   types: VisitTypes,
+  options: &'o ConvertCssOptions,
+  placeholders: &'o Placeholders,
   host_css_vec: Vec<String>,
+  // @import 的地址, 占位符中记录的是下标
+  imports: Vec<String>,
+  // 样式中出现的类名
+  classes: Vec<ScopedClass>,
+  // class_name_pattern 中 [hash] 的值, 由文件路径和内容计算
+  class_hash: String,
+  // 原始输入和解析前替换过的文本, 用于还原 url() 的位置
+  css: &'o str,
+  source: &'o TrackedText,
+  assets: Vec<Asset>,
+  // 访问规则时产生的警告, 例如无法改写的 url()
+  warnings: Vec<Diagnostic>,
 }
 
-impl FactoryVisitor {
-  fn replace_rpx_token(&self, token: &mut Token) {
-    if let Token::Dimension {
-      ref mut value,
-      unit,
-      ..
-    } = token
-    {
-      if unit == &"rpx" {
-        // 把当前 token 替换成  RPX_FUNC(value) 的形式
-        *token = Token::String(format!("{}({})", RPX_FUNC, value).into());
+impl FactoryVisitor<'_> {
+  // 解析位置 (行从 0 开始, 列从 1 开始) => 原始输入中的位置 (从 1 开始)
+  fn original_position(&self, line: u32, column: u32) -> (u32, u32) {
+    let offset = position_to_offset(&self.source.text, line, column.saturating_sub(1), true);
+    let (line, column) = offset_to_position(self.css, self.source.unmap_offset(offset));
+    (line + 1, column + 1)
+  }
+
+  // 按单位表转换 token 中的尺寸, 返回 None 时保持不变
+  fn convert_dimension<'t>(&self, value: f32, unit: &str) -> Option<TokenOrValue<'t>> {
+    match self.options.units.get(&unit.to_ascii_lowercase())? {
+      // 把当前 token 替换成单位转换的占位符
+      UnitConversion::Runtime(func) => Some(TokenOrValue::Token(Token::String(
+        self.placeholders.unit(func, &value.to_string()).into(),
+      ))),
+      UnitConversion::Static { factor, unit } => {
+        static_length(value * factor, unit).map(TokenOrValue::Length)
       }
     }
   }
 
+  // 编译时生成的类名: .a => .a_<hash>
+  fn hashed_class_name(&self, name: &str) -> Option<String> {
+    let pattern = self.options.class_name_pattern.as_deref()?;
+    let file_name = Path::new(&self.options.filename)
+      .file_stem()
+      .map(|stem| stem.to_string_lossy())
+      .unwrap_or_default();
+    let replaced =
+      CLASS_NAME_PATTERN_REGEX.replace_all(pattern, |caps: &Captures| match &caps[1] {
+        "local" => name.to_string(),
+        "name" => file_name.to_string(),
+        _ => self.class_hash.clone(),
+      });
+    Some(replaced.into_owned())
+  }
+
+  fn record_class(&mut self, name: &str) {
+    if self.classes.iter().any(|class| class.name == name) {
+      return;
+    }
+    let scoped = match (self.hashed_class_name(name), self.options.prefix_class) {
+      (Some(hashed), _) => vec![Segment::Text(hashed)],
+      (None, true) => vec![Segment::Prefix, Segment::Text(name.to_string())],
+      (None, false) => vec![Segment::Text(name.to_string())],
+    };
+    self.classes.push(ScopedClass {
+      name: name.to_string(),
+      scoped,
+    });
+  }
+
   fn is_host_selector(&self, selector: &Selector) -> bool {
     if selector.iter().selector_length() != 1 {
       return false;
@@ -61,7 +121,7 @@ impl FactoryVisitor {
       {
         return local_name == &Ident::from("is")
           && operator == &AttrSelectorOperator::Equal
-          && value == &CSSString::from(HOST);
+          && value == &CSSString::from(self.placeholders.host());
       }
       false
     })
@@ -69,9 +129,10 @@ impl FactoryVisitor {
 
   fn has_single_selector(&self, selectors: &SelectorList) -> bool {
     // 判断列表里是否有单个 :host 选择器
-    selectors.0.iter().any(|selector| {
-      return self.is_host_selector(selector);
-    })
+    selectors
+      .0
+      .iter()
+      .any(|selector| self.is_host_selector(selector))
   }
 
   fn remove_single_selector<'i>(&self, selectors: &SelectorList<'i>) -> SelectorList<'i> {
@@ -80,8 +141,8 @@ impl FactoryVisitor {
       selectors
         .0
         .iter()
-        .cloned()
         .filter(|selector| !self.is_host_selector(selector))
+        .cloned()
         .collect(),
     )
   }
@@ -90,18 +151,51 @@ impl FactoryVisitor {
     Selector::from(vec![self.create_host_component()])
   }
 
+  /*
+   * 标签选择器的替换结果, 返回 None 时保持不变
+   * 先按 tag_map 替换别名, 再转换成 [meta:tag=div] 或 tag("div")
+   */
+  fn create_tag_component<'c>(&self, name: &str) -> Option<Component<'c>> {
+    let local_name = |name: String| {
+      Component::LocalName(LocalName {
+        name: name.clone().into(),
+        lower_name: name.into(),
+      })
+    };
+    let alias = match self.options.tag_map.get(name) {
+      Some(TagReplacement::Unsupported(marker)) => return Some(local_name(marker.clone())),
+      Some(TagReplacement::Alias(alias)) => Some(alias.as_str()),
+      None => None,
+    };
+    let tag_name = alias.unwrap_or(name);
+
+    if self.options.runtime_tag {
+      Some(local_name(self.placeholders.tag(tag_name)))
+    } else if self.options.replace_tag {
+      Some(Component::AttributeInNoNamespace {
+        local_name: Ident::from(self.options.meta_tag.clone()),
+        operator: AttrSelectorOperator::Equal,
+        value: CSSString::from(tag_name.to_string()),
+        case_sensitivity: ParsedCaseSensitivity::CaseSensitive,
+        never_matches: false,
+      })
+    } else {
+      alias.map(|alias| local_name(alias.to_string()))
+    }
+  }
+
   fn create_host_component<'c>(&self) -> Component<'c> {
     Component::AttributeInNoNamespace {
       local_name: Ident::from("is"),
       operator: AttrSelectorOperator::Equal,
-      value: CSSString::from(HOST),
+      value: CSSString::from(self.placeholders.host()),
       case_sensitivity: ParsedCaseSensitivity::CaseSensitive,
       never_matches: false,
     }
   }
 }
 
-impl<'i> Visitor<'i> for FactoryVisitor {
+impl<'i> Visitor<'i> for FactoryVisitor<'_> {
   type Error = Box<dyn Error>;
 
   fn visit_types(&self) -> VisitTypes {
@@ -111,14 +205,16 @@ impl<'i> Visitor<'i> for FactoryVisitor {
   fn visit_rule<'a>(&mut self, rule: &'a mut CssRule<'i>) -> Result<(), Self::Error> {
     match rule {
       CssRule::Import(ref import_rule) => {
-        // @import url('./a.css'); => @import-style ("./a.css")
+        // @import url('./a.css'); => @<import> 0;
+        let index = self.imports.len();
+        self.imports.push(import_rule.url.to_string());
         let new_rule = CssRule::Unknown(UnknownAtRule {
-          name: IMPORT_STYLE.into(),
-          prelude: TokenList(vec![
-            TokenOrValue::Token(Token::ParenthesisBlock),
-            TokenOrValue::Token(Token::String(import_rule.url.to_string().into())),
-            TokenOrValue::Token(Token::CloseParenthesis),
-          ]),
+          name: self.placeholders.import().into(),
+          prelude: TokenList(vec![TokenOrValue::Token(Token::Number {
+            has_sign: false,
+            value: index as f32,
+            int_value: Some(index as i32),
+          })]),
           block: None,
           loc: import_rule.loc,
         });
@@ -140,9 +236,11 @@ impl<'i> Visitor<'i> for FactoryVisitor {
 
         // 移除后，如果没有选择器了，则将当前 rule 设置为 Ignored
         // 并将 host_css 添加到 host_css_vec 中
-        if omit_single_selectors.0.len() == 0 {
+        if omit_single_selectors.0.is_empty() {
           let cloned_rule = rule.clone();
-          let host_css = cloned_rule.to_css_string(get_printer_options()).unwrap();
+          let host_css = cloned_rule
+            .to_css_string(get_printer_options(self.options.targets))
+            .unwrap();
 
           self.host_css_vec.push(host_css);
           *rule = CssRule::Ignored;
@@ -154,9 +252,11 @@ impl<'i> Visitor<'i> for FactoryVisitor {
           single_selectors.0.push(self.create_host_selector());
           clone_style.selectors = single_selectors;
 
-          self
-            .host_css_vec
-            .push(clone_style.to_css_string(get_printer_options()).unwrap());
+          self.host_css_vec.push(
+            clone_style
+              .to_css_string(get_printer_options(self.options.targets))
+              .unwrap(),
+          );
         }
       }
     }
@@ -164,44 +264,36 @@ impl<'i> Visitor<'i> for FactoryVisitor {
   }
 
   fn visit_selector(&mut self, selector: &mut Selector<'i>) -> Result<(), Self::Error> {
-    // 修改 selector 的样式名, 添加一个 PREFIX 前缀
+    // 修改 selector 的样式名, 添加一个 prefix 占位符
     if self.types.contains(VisitTypes::SELECTORS) {
       for component in &mut selector.iter_mut_raw_match_order() {
         match component {
-          // 将类名替换成 PREFIX 类名
+          // 将类名替换成 prefix 占位符 + 类名
           Component::Class(class) => {
-            *class = format!("{}{}", PREFIX, class).into();
+            self.record_class(class);
+            if let Some(hashed) = self.hashed_class_name(class) {
+              *class = hashed.into();
+            } else if self.options.prefix_class {
+              *class = format!("{}{}", self.placeholders.prefix(), class).into();
+            }
           }
 
-          // 处理 * 选择器 * => unsupported-star
-          Component::ExplicitUniversalType => {
-            *component = Component::LocalName(LocalName {
-              name: UNSUPPORTED_STAR.into(),
-              lower_name: UNSUPPORTED_STAR.into(),
-            });
+          // 处理 * 选择器, 只在 tag_map 中配置了 * 时替换  * => unsupported-star
+          Component::ExplicitUniversalType if self.options.tag_map.contains_key("*") => {
+            if let Some(replaced) = self.create_tag_component("*") {
+              *component = replaced;
+            }
           }
 
-          // 处理 :host 选择器 :host => [is=HOST]
+          // 处理 :host 选择器 :host => [is=<host>]
           Component::Host(_host) => {
             *component = self.create_host_component();
           }
 
           // 将标签替换成 attribute 属性选择符  div => [meta:tag=div]
           Component::LocalName(local_name) => {
-            // 如果是 web-view 标签, 则修改成 unsupported-web-view
-            if local_name.name == "web-view" {
-              *component = Component::LocalName(LocalName {
-                name: UNSUPPORTED_WEB_VIEW.into(),
-                lower_name: UNSUPPORTED_WEB_VIEW.into(),
-              });
-            } else {
-              *component = Component::AttributeInNoNamespace {
-                local_name: Ident::from(META_TAG),
-                operator: AttrSelectorOperator::Equal,
-                value: CSSString::from(local_name.name.to_string()),
-                case_sensitivity: ParsedCaseSensitivity::CaseSensitive,
-                never_matches: false,
-              };
+            if let Some(replaced) = self.create_tag_component(&local_name.name) {
+              *component = replaced;
             }
           }
           // 递归处理子选择器
@@ -226,13 +318,44 @@ impl<'i> Visitor<'i> for FactoryVisitor {
     Ok(())
   }
 
+  // lightningcss 解析为 typed value 的长度只支持编译时转换  1rem => 16px
+  fn visit_length(&mut self, length: &mut LengthValue) -> Result<(), Self::Error> {
+    let (value, unit) = length.to_unit_value();
+    if let Some(UnitConversion::Static { factor, unit }) = self.options.units.get(unit) {
+      if let Some(converted) = static_length(value * factor, unit) {
+        *length = converted;
+      }
+    }
+    Ok(())
+  }
+
+  fn visit_url(&mut self, url: &mut Url<'i>) -> Result<(), Self::Error> {
+    // url 的 loc 行列都从 1 开始
+    let (line, column) = self.original_position(url.loc.line - 1, url.loc.column);
+    let asset = Asset::new(&url.url, &self.options.filename, line, column);
+    let root = Path::new(&self.options.filename)
+      .parent()
+      .unwrap_or(Path::new(""));
+    self
+      .warnings
+      .extend(self.options.assets.apply(url, &asset, root)?);
+    self.assets.push(asset);
+    Ok(())
+  }
+
   fn visit_token(&mut self, token: &mut TokenOrValue<'i>) -> Result<(), Self::Error> {
     match token {
-      TokenOrValue::Token(token) => {
-        if let Token::Dimension { .. } = token {
-          self.replace_rpx_token(token);
+      TokenOrValue::Token(Token::Dimension { value, unit, .. }) => {
+        if let Some(converted) = self.convert_dimension(*value, unit) {
+          *token = converted;
         }
       }
+      TokenOrValue::Length(length) => {
+        self.visit_length(length)?;
+      }
+      TokenOrValue::Url(url) => {
+        self.visit_url(url)?;
+      }
       TokenOrValue::Function(function) => {
         function.arguments.visit_children(self)?;
       }
@@ -250,68 +373,468 @@ impl<'i> Visitor<'i> for FactoryVisitor {
 
 #[derive(Debug, Clone)]
 pub struct ConvertResult {
-  pub css: String,
-  pub host_css: Option<String>,
+  pub css: CssSegments,
+  pub host_css: Option<CssSegments>,
+  // css 到原始输入的 source map, 不包含 host_css
+  pub source_map: Option<SourceMap>,
+  pub warnings: Vec<Diagnostic>,
+  pub classes: Vec<ScopedClass>,
+  // url() 引用的资源, 按出现的顺序
+  pub assets: Vec<Asset>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConvertCssOptions {
+  pub targets: Targets,
+  // .a => "." + prefix + "a"
+  pub prefix_class: bool,
+  // 单位转换表, key 为小写的单位名  100rpx => rpx(100) + "px"
+  pub units: HashMap<String, UnitConversion>,
+  // div => [meta:tag=div]
+  pub replace_tag: bool,
+  // div => tag("div"), 优先于 replace_tag
+  pub runtime_tag: bool,
+  pub meta_tag: String,
+  // 标签替换表, * 表示通配选择器  web-view => unsupported-web-view
+  pub tag_map: HashMap<String, TagReplacement>,
+  pub source_map: bool,
+  pub filename: String,
+  // 跳过无效的规则和声明, 并收集为 warnings
+  pub error_recovery: bool,
+  // 编译时按模板重命名类名, 代替运行时的 prefix, 例如 "[local]_[hash]"
+  pub class_name_pattern: Option<String>,
+  // url() 的改写
+  pub assets: AssetOptions,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnitConversion {
+  // 运行时调用 options 中的同名函数  100rpx => rpx(100) + "px"
+  Runtime(String),
+  // 编译时按倍数转换成标准单位  1rem => 16px
+  Static { factor: f32, unit: String },
+}
+
+impl UnitConversion {
+  // 设计稿宽度已知时, 编译时把 rpx 转换成 vw  100rpx => 100 / design_width * 100vw
+  pub fn rpx_to_vw(design_width: f32) -> Self {
+    UnitConversion::Static {
+      factor: 100.0 / design_width,
+      unit: "vw".to_string(),
+    }
+  }
+
+  // 设备宽度也已知时转换成 px  100rpx => 100 * device_width / design_width px
+  pub fn rpx_to_px(design_width: f32, device_width: f32) -> Self {
+    UnitConversion::Static {
+      factor: device_width / design_width,
+      unit: "px".to_string(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagReplacement {
+  // view => div, 替换后仍按 replace_tag / runtime_tag 处理
+  Alias(String),
+  // web-view => unsupported-web-view, 原样输出
+  Unsupported(String),
+}
+
+impl TagReplacement {
+  // 默认的不支持标记: * => unsupported-star, web-view => unsupported-web-view
+  pub fn unsupported(tag: &str) -> Self {
+    match tag {
+      "*" => TagReplacement::Unsupported("unsupported-star".to_string()),
+      tag => TagReplacement::Unsupported(format!("unsupported-{}", tag)),
+    }
+  }
+}
+
+impl Default for ConvertCssOptions {
+  fn default() -> Self {
+    ConvertCssOptions {
+      targets: get_targets(),
+      prefix_class: true,
+      units: HashMap::from([(
+        "rpx".to_string(),
+        UnitConversion::Runtime("rpx".to_string()),
+      )]),
+      replace_tag: true,
+      runtime_tag: false,
+      meta_tag: META_TAG.to_string(),
+      tag_map: HashMap::from([
+        ("*".to_string(), TagReplacement::unsupported("*")),
+        (
+          "web-view".to_string(),
+          TagReplacement::unsupported("web-view"),
+        ),
+      ]),
+      source_map: false,
+      filename: String::new(),
+      error_recovery: false,
+      class_name_pattern: None,
+      assets: AssetOptions::default(),
+    }
+  }
 }
 
-pub fn convert_css(css: String) -> Result<ConvertResult, Box<dyn Error>> {
+pub fn convert_css(css: String, options: &ConvertCssOptions) -> Result<ConvertResult, Diagnostic> {
   if css.is_empty() {
+    let source_map = match options.source_map {
+      true => Some(
+        remap_source_map(
+          &SourceMap::new("/"),
+          &css,
+          &TrackedText::new(""),
+          &options.filename,
+        )
+        .map_err(source_map_diagnostic)?,
+      ),
+      false => None,
+    };
     return Ok(ConvertResult {
-      css: "".to_string(),
+      css: CssSegments::default(),
       host_css: None,
+      source_map,
+      warnings: Vec::new(),
+      classes: Vec::new(),
+      assets: Vec::new(),
     });
   }
 
+  let placeholders = Placeholders::new(&css);
+
+  // media query 不支持 rpx 等单位, 解析前先替换成 env(--<unit>rpx,value)
+  let source = replace_media_units(&css, &placeholders, &options.units);
+
   // 1. 解析 CSS（处理解析错误）
-  let mut stylesheet =
-    StyleSheet::parse(&css, get_parser_options()).map_err(|e| format!("Parse error: {}", e))?;
+  let warnings = Arc::new(RwLock::new(Vec::new()));
+  let parser_options = ParserOptions {
+    filename: options.filename.clone(),
+    error_recovery: options.error_recovery,
+    warnings: options.error_recovery.then(|| warnings.clone()),
+    ..get_parser_options()
+  };
+  let mut stylesheet = StyleSheet::parse(&source.text, parser_options).map_err(|e| {
+    located_diagnostic(DiagnosticKind::Parse, &e, &css, &source).context("Parse error")
+  })?;
+
+  let warnings = warnings
+    .read()
+    .unwrap()
+    .iter()
+    .map(|e| located_diagnostic(DiagnosticKind::Parse, e, &css, &source).warning())
+    .collect::<Vec<_>>();
 
   let mut visitor = FactoryVisitor {
     types: VisitTypes::all(),
+    options,
+    placeholders: &placeholders,
     host_css_vec: Vec::new(),
+    imports: Vec::new(),
+    classes: Vec::new(),
+    class_hash: md5_hash(&format!("{}\n{}", options.filename, css))[..8].to_string(),
+    css: &css,
+    source: &source,
+    assets: Vec::new(),
+    warnings: Vec::new(),
   };
 
   // 2. 遍历规则（处理访问错误）
   stylesheet
     .visit(&mut visitor)
-    .map_err(|e| format!("Visit error: {}", e))?;
+    .map_err(|e| Diagnostic::new(DiagnosticKind::Visit, e.to_string()).context("Visit error"))?;
 
   stylesheet
-    .minify(get_minify_options())
-    .map_err(|e| format!("Minify error: {}", e))?;
+    .minify(get_minify_options(options.targets))
+    .map_err(|e| {
+      located_diagnostic(DiagnosticKind::Minify, &e, &css, &source).context("Minify error")
+    })?;
 
   // 3. 生成 CSS（处理序列化错误）
+  let mut source_map = options.source_map.then(|| SourceMap::new("/"));
   let res = stylesheet
-    .to_css(get_printer_options())
-    .map_err(|e| format!("Serialize error: {}", e))?;
+    .to_css(PrinterOptions {
+      source_map: source_map.as_mut(),
+      ..get_printer_options(options.targets)
+    })
+    .map_err(|e| {
+      located_diagnostic(DiagnosticKind::Serialize, &e, &css, &source).context("Serialize error")
+    })?;
+
+  let source_map = match source_map {
+    Some(map) => {
+      Some(remap_source_map(&map, &css, &source, &options.filename).map_err(source_map_diagnostic)?)
+    }
+    None => None,
+  };
+
+  // 类名按字母排序, 输出与规则的顺序无关
+  let mut classes = visitor.classes;
+  classes.sort_by(|a, b| a.name.cmp(&b.name));
 
-  let host_css_string = process_host_css(&visitor.host_css_vec)?;
+  let host_css_string = process_host_css(&visitor.host_css_vec, options.targets)?;
 
-  // 4. 返回成功结果
+  // 4. 按占位符切分成片段
+  let split = |printed: String, imports: &[String]| {
+    placeholders
+      .split(printed, imports)
+      .map_err(|e| Diagnostic::new(DiagnosticKind::Serialize, e).context("Serialize error"))
+  };
+  let css_segments = split(res.code, &visitor.imports)?;
+  let host_segments = match host_css_string {
+    Some(host_css) => Some(split(host_css, &[])?),
+    None => None,
+  };
+
+  // 5. 返回成功结果
   Ok(ConvertResult {
-    css: res.code,
-    host_css: host_css_string,
+    css: css_segments,
+    host_css: host_segments,
+    source_map,
+    warnings: warnings
+      .into_iter()
+      .chain(
+        visitor
+          .warnings
+          .into_iter()
+          .map(|warning| warning.with_code_frame(&css)),
+      )
+      .collect(),
+    classes,
+    assets: visitor.assets,
   })
 }
 
+pub(crate) static CLASS_NAME_PATTERN_REGEX: Lazy<Regex> = lazy_regex!(r"\[(\w+)\]");
+
+// 编译时转换后的长度, 目标单位必须是 lightningcss 支持的标准单位
+pub(crate) fn static_length(value: f32, unit: &str) -> Option<LengthValue> {
+  LengthValue::parse_string(&format!("{}{}", value, unit)).ok()
+}
+
+// @media (max-width: 600rpx) => @media (max-width: env(--<unit>rpx,600))
+// 同样处理 @import 的 media 和 @container 的条件
+fn replace_media_units(
+  css: &str,
+  placeholders: &Placeholders,
+  units: &HashMap<String, UnitConversion>,
+) -> TrackedText {
+  let replacements = media_unit_values(css, units)
+    .into_iter()
+    .map(|(range, value, conversion)| {
+      let replacement = match conversion {
+        UnitConversion::Runtime(func) => placeholders.media_unit(func, &value),
+        // 600rpx => 80vw
+        UnitConversion::Static { factor, unit } => {
+          let number = value.parse::<f32>().unwrap_or_default();
+          format!("{}{}", number * factor, unit)
+        }
+      };
+      (range, replacement)
+    })
+    .collect();
+
+  let mut tracked = TrackedText::new(css);
+  tracked.replace_ranges(replacements);
+  tracked
+}
+
+/*
+ * 打包时 lightningcss 同样无法解析 media query 中的 rpx 等单位
+ * 先替换成以单位名命名的占位符, 打包后再由 Placeholders::restore_media_units 还原
+ */
+pub(crate) fn protect_media_units(
+  css: &str,
+  placeholders: &Placeholders,
+  units: &HashMap<String, UnitConversion>,
+) -> TrackedText {
+  let replacements = media_unit_values(css, units)
+    .into_iter()
+    .map(|(range, value, _)| {
+      let unit = &css[range.start + value.len()..range.end];
+      (range, placeholders.media_unit(unit, &value))
+    })
+    .collect();
+
+  let mut tracked = TrackedText::new(css);
+  tracked.replace_ranges(replacements);
+  tracked
+}
+
+// media query 中需要转换的 <数值><单位>, 返回 (位置, 数值, 转换方式)
+fn media_unit_values<'u>(
+  css: &str,
+  units: &'u HashMap<String, UnitConversion>,
+) -> Vec<(Range<usize>, String, &'u UnitConversion)> {
+  // 标准单位由 lightningcss 解析, 在 visit_length 中转换
+  let is_media_unit = |unit: &str| match units.get(&unit.to_ascii_lowercase()) {
+    Some(UnitConversion::Runtime(_)) => true,
+    Some(UnitConversion::Static { .. }) => static_length(1.0, unit).is_none(),
+    None => false,
+  };
+  if !units.keys().any(|unit| is_media_unit(unit)) {
+    return Vec::new();
+  }
+
+  let mut dimensions = Vec::new();
+  let mut input = ParserInput::new(css);
+  scan_rules(&mut Parser::new(&mut input), &mut dimensions);
+
+  dimensions
+    .into_iter()
+    .filter(|(_, unit)| is_media_unit(unit))
+    .filter_map(|(range, unit)| {
+      let value = css[range.clone()].get(..range.len().checked_sub(unit.len())?)?;
+      let conversion = units.get(&unit.to_ascii_lowercase())?;
+      Some((range, value.to_string(), conversion))
+    })
+    .collect()
+}
+
+// 条件中可能包含长度的 at-rule
+fn has_media_prelude(name: &str) -> bool {
+  ["media", "import", "container"]
+    .iter()
+    .any(|rule| name.eq_ignore_ascii_case(rule))
+}
+
+/*
+ * 按 css token 查找 at-rule, 跳过字符串和注释中的文本
+ * @param dimensions: 收集条件中的 (位置, 单位)
+ */
+fn scan_rules(parser: &mut Parser, dimensions: &mut Vec<(Range<usize>, String)>) {
+  while let Ok(token) = parser.next() {
+    match token.clone() {
+      CssToken::AtKeyword(name) if has_media_prelude(&name) => scan_prelude(parser, dimensions),
+      CssToken::CurlyBracketBlock => {
+        let _ = parser.parse_nested_block(|parser| {
+          scan_rules(parser, dimensions);
+          Ok::<_, CssParseError<()>>(())
+        });
+      }
+      _ => {}
+    }
+  }
+}
+
+// 条件到 ; 或 { 结束, url() / supports() / layer() 中的内容不是 media query
+fn scan_prelude(parser: &mut Parser, dimensions: &mut Vec<(Range<usize>, String)>) {
+  loop {
+    let start = parser.position().byte_index();
+    let Ok(token) = parser.next_including_whitespace_and_comments() else {
+      return;
+    };
+    match token.clone() {
+      CssToken::Dimension { unit, .. } => {
+        dimensions.push((start..parser.position().byte_index(), unit.to_string()));
+      }
+      CssToken::Function(name)
+        if ["url", "supports", "layer"]
+          .iter()
+          .any(|func| name.eq_ignore_ascii_case(func)) => {}
+      CssToken::Function(_) | CssToken::ParenthesisBlock | CssToken::SquareBracketBlock => {
+        let _ = parser.parse_nested_block(|parser| {
+          scan_prelude(parser, dimensions);
+          Ok::<_, CssParseError<()>>(())
+        });
+      }
+      CssToken::Semicolon => return,
+      CssToken::CurlyBracketBlock => {
+        let _ = parser.parse_nested_block(|parser| {
+          scan_rules(parser, dimensions);
+          Ok::<_, CssParseError<()>>(())
+        });
+        return;
+      }
+      _ => {}
+    }
+  }
+}
+
+// 错误位置是替换 rpx 之后的位置, 需要还原到输入的 CSS 上
+fn located_diagnostic<T: Display>(
+  kind: DiagnosticKind,
+  error: &lightningcss::error::Error<T>,
+  css: &str,
+  source: &TrackedText,
+) -> Diagnostic {
+  let mut diagnostic = Diagnostic::from_css_error(kind, error);
+  if let (Some(line), Some(column)) = (diagnostic.line, diagnostic.column) {
+    let offset = position_to_offset(&source.text, line - 1, column.saturating_sub(1), true);
+    let (line, column) = offset_to_position(css, source.unmap_offset(offset));
+    diagnostic.line = Some(line + 1);
+    diagnostic.column = Some(column + 1);
+  }
+  diagnostic.with_code_frame(css)
+}
+
+fn source_map_diagnostic(error: Box<dyn Error>) -> Diagnostic {
+  Diagnostic::new(DiagnosticKind::Serialize, error.to_string()).context("Source map error")
+}
+
+// 解析前替换过 media query 中的 rpx, 需要把 source map 的原始位置还原到输入的 CSS 上
+fn remap_source_map(
+  map: &SourceMap,
+  css: &str,
+  source: &TrackedText,
+  filename: &str,
+) -> Result<SourceMap, Box<dyn Error>> {
+  let mut result = SourceMap::new("/");
+  let source_index = result.add_source(filename);
+  result.set_source_content(source_index as usize, css)?;
+
+  for mapping in map.get_mappings() {
+    let original = mapping.original.map(|location| {
+      let offset = position_to_offset(
+        &source.text,
+        location.original_line,
+        location.original_column,
+        true,
+      );
+      let (original_line, original_column) = offset_to_position(css, source.unmap_offset(offset));
+      OriginalLocation {
+        original_line,
+        original_column,
+        source: source_index,
+        name: None,
+      }
+    });
+    result.add_mapping(mapping.generated_line, mapping.generated_column, original);
+  }
+
+  Ok(result)
+}
+
 // Extract host CSS processing into a separate function
-fn process_host_css(host_css_vec: &[String]) -> Result<Option<String>, Box<dyn Error>> {
+fn process_host_css(
+  host_css_vec: &[String],
+  targets: Targets,
+) -> Result<Option<String>, Diagnostic> {
   if host_css_vec.is_empty() {
     return Ok(None);
   }
 
   let host_css_css = host_css_vec.join("\n");
 
-  let mut host_stylesheet = StyleSheet::parse(&host_css_css, get_parser_options())
-    .map_err(|e| format!("Parse host error: {}", e))?;
+  // host css 是生成的代码, 错误位置没有意义
+  let mut host_stylesheet =
+    StyleSheet::parse(&host_css_css, get_parser_options()).map_err(|e| {
+      Diagnostic::new(DiagnosticKind::Parse, e.to_string()).context("Parse host error")
+    })?;
 
   host_stylesheet
-    .minify(get_minify_options())
-    .map_err(|e| format!("Minify host error: {}", e))?;
+    .minify(get_minify_options(targets))
+    .map_err(|e| {
+      Diagnostic::new(DiagnosticKind::Minify, e.to_string()).context("Minify host error")
+    })?;
 
   let host_css_css = host_stylesheet
-    .to_css(get_printer_options())
-    .map_err(|e| format!("Serialize host error: {}", e))?;
+    .to_css(get_printer_options(targets))
+    .map_err(|e| {
+      Diagnostic::new(DiagnosticKind::Serialize, e.to_string()).context("Serialize host error")
+    })?;
 
   Ok(Some(host_css_css.code))
 }
@@ -336,7 +859,7 @@ mod tests {
       "#
     };
 
-    let result = convert_css(input.to_string());
+    let result = convert_css(input.to_string(), &ConvertCssOptions::default());
     assert_snapshot!(result.unwrap().css);
   }
 
@@ -346,48 +869,52 @@ mod tests {
             color: red;
         }"
     .to_string();
-    assert_snapshot!(convert_css(input).unwrap().css);
+    assert_snapshot!(
+      convert_css(input, &ConvertCssOptions::default())
+        .unwrap()
+        .css
+    );
   }
 
   #[test]
   fn test_is_selector() {
     let input = ".a:is(.b, .c) { height: calc(50rpx - var(--abc, 100rpx)); }".to_string();
-    let result = convert_css(input.to_string());
+    let result = convert_css(input.to_string(), &ConvertCssOptions::default());
     assert_snapshot!(result.unwrap().css);
   }
 
   #[test]
   fn test_where_selector() {
     let input = ".a:where(.b, .c) { color: green; }".to_string();
-    let result = convert_css(input.to_string());
+    let result = convert_css(input.to_string(), &ConvertCssOptions::default());
     assert_snapshot!(result.unwrap().css);
   }
 
   #[test]
   fn test_has_selector() {
     let input = ".a:has(.b) { color: purple; }".to_string();
-    let result = convert_css(input.to_string());
+    let result = convert_css(input.to_string(), &ConvertCssOptions::default());
     assert_snapshot!(result.unwrap().css);
   }
 
   #[test]
   fn test_star_selector() {
     let input = "* { color: black; } .a * {height: 100px;}".to_string();
-    let result = convert_css(input.to_string());
+    let result = convert_css(input.to_string(), &ConvertCssOptions::default());
     assert_snapshot!(result.unwrap().css);
   }
 
   #[test]
   fn test_host_selector() {
     let input = "web-view :host { color: black; }".to_string();
-    let result = convert_css(input.to_string());
+    let result = convert_css(input.to_string(), &ConvertCssOptions::default());
     assert_snapshot!(result.unwrap().css);
   }
 
   #[test]
   fn split_host_selector() {
     let input = ":host, .body { height: 20rpx; }".to_string();
-    let result = convert_css(input.to_string());
+    let result = convert_css(input.to_string(), &ConvertCssOptions::default());
     let result_unwrapped = result.unwrap();
     assert_snapshot!(result_unwrapped.css);
     assert_snapshot!(result_unwrapped.host_css.unwrap_or_default());
@@ -396,23 +923,23 @@ mod tests {
   #[test]
   fn test_import() {
     let input = "@import url('./a.css');".to_string();
-    let result = convert_css(input.to_string());
+    let result = convert_css(input.to_string(), &ConvertCssOptions::default());
     assert_snapshot!(result.unwrap().css);
   }
 
   #[test]
   fn test_remove_single_host() {
     let input = ":host { color: black; }".to_string();
-    let result = convert_css(input.to_string());
+    let result = convert_css(input.to_string(), &ConvertCssOptions::default());
     let result_unwrapped = result.unwrap();
-    assert!(result_unwrapped.css.is_empty());
+    assert!(result_unwrapped.css.segments.is_empty());
     assert_snapshot!(result_unwrapped.host_css.unwrap_or_default());
   }
 
   #[test]
   fn test_remove_multi_host() {
     let input = ":host { color: black; width: 100rpx } :host { height: 20rpx; }".to_string();
-    let result = convert_css(input.to_string());
+    let result = convert_css(input.to_string(), &ConvertCssOptions::default());
     let result_unwrapped = result.unwrap();
     assert_snapshot!(result_unwrapped.css);
     assert_snapshot!(result_unwrapped.host_css.unwrap_or_default());
@@ -430,7 +957,7 @@ mod tests {
       }
     "#}
     .to_string();
-    let result = convert_css(input.to_string());
+    let result = convert_css(input.to_string(), &ConvertCssOptions::default());
     let result_unwrapped = result.unwrap();
     assert_snapshot!(result_unwrapped.css);
     assert_snapshot!(result_unwrapped.host_css.unwrap_or_default());
@@ -448,7 +975,7 @@ mod tests {
       }
     "#}
     .to_string();
-    let result = convert_css(input.to_string());
+    let result = convert_css(input.to_string(), &ConvertCssOptions::default());
     let result_unwrapped = result.unwrap();
     assert_snapshot!(result_unwrapped.css);
     assert_snapshot!(result_unwrapped.host_css.unwrap_or_default());
@@ -486,12 +1013,11 @@ mod tests {
     }
     .to_string();
 
-    let result = convert_css(input.to_string());
+    let result = convert_css(input.to_string(), &ConvertCssOptions::default());
     assert_snapshot!(result.unwrap().css);
   }
 
   #[test]
-  // 不支持 media query 进行 rpx 设置会报错
   fn test_media_query() {
     let input = indoc! {r#"
       @media screen and (max-width: 600rpx) {
@@ -501,16 +1027,254 @@ mod tests {
       }
     "#}
     .to_string();
-    let result = convert_css(input);
+    let result = convert_css(input, &ConvertCssOptions::default());
+    assert_snapshot!(result.unwrap().css);
+  }
+
+  #[test]
+  fn test_media_query_range() {
+    let input = indoc! {r#"
+      @media (min-width: 37.5rpx) and (max-width: .5rpx), print and (min-height: 100px) {
+        .a { color: red; }
+      }
+      .b { width: 100rpx; }
+    "#}
+    .to_string();
+    let result = convert_css(input, &ConvertCssOptions::default());
+    assert_snapshot!(result.unwrap().css);
+  }
+
+  #[test]
+  // 字符串和注释中的 @media 不是 media query, 不替换
+  fn test_media_query_in_string_and_comment() {
+    let input = indoc! {r#"
+      /* @media (max-width: 10rpx) { } */
+      .a { content: "@media (max-width: 10rpx)"; }
+      @media (max-width: 20rpx) { .b { color: red; } }
+    "#};
+    let units = ConvertCssOptions::default().units;
+    let placeholders = Placeholders::new(input);
+    let result = replace_media_units(input, &placeholders, &units);
+    assert!(result.text.contains("/* @media (max-width: 10rpx) { } */"));
+    assert!(result.text.contains(r#""@media (max-width: 10rpx)""#));
+    assert!(!result.text.contains("20rpx"));
+
+    let result = convert_css(input.to_string(), &ConvertCssOptions::default());
+    assert_snapshot!(result.unwrap().css);
+  }
+
+  #[test]
+  fn test_container_query() {
+    let input = indoc! {r#"
+      .card { container-type: inline-size; }
+      @container (min-width: 100rpx) {
+        .title { font-size: 24rpx; }
+      }
+    "#}
+    .to_string();
+    let result = convert_css(input, &ConvertCssOptions::default());
+    assert_snapshot!(result.unwrap().css);
+  }
+
+  #[test]
+  // 打包前 @import 的 media 中的单位替换为占位符
+  fn test_protect_import_media_units() {
+    let input = indoc! {r#"
+      @import url("a.css") screen and (min-width: 100rpx);
+      @import "b.css" supports(width: 10rpx) (max-width: 2.5rpx);
+      @import url(c-10rpx.css);
+    "#};
+    let units = ConvertCssOptions::default().units;
+    let placeholders = Placeholders::new(input);
+    let result = protect_media_units(input, &placeholders, &units);
+    assert_eq!(
+      result.text,
+      input
+        .replace("100rpx", &placeholders.media_unit("rpx", "100"))
+        .replace("2.5rpx", &placeholders.media_unit("rpx", "2.5"))
+    );
+  }
+
+  #[test]
+  // 关闭 rpx 替换时, media query 中的 rpx 依然会报错
+  fn test_media_query_without_rpx() {
+    let input = "@media (max-width: 600rpx) { .a { color: red; } }".to_string();
+    let options = ConvertCssOptions {
+      units: HashMap::new(),
+      ..ConvertCssOptions::default()
+    };
+    let result = convert_css(input, &options);
     match result {
       Ok(_) => panic!("Expected an error, but got Ok"),
       Err(e) => {
-        println!("Error: {}", e);
         assert_snapshot!(e.to_string());
       }
     }
   }
 
+  #[test]
+  fn test_targets() {
+    let input = ".a { backdrop-filter: blur(2px); user-select: none; }";
+    let default_result = convert_css(input.to_string(), &ConvertCssOptions::default());
+    assert_snapshot!(default_result.unwrap().css);
+
+    let targets = crate::options::resolve_targets(Some(&["chrome >= 120".to_string()]), None);
+    let modern_result = convert_css(
+      input.to_string(),
+      &ConvertCssOptions {
+        targets: targets.unwrap(),
+        ..ConvertCssOptions::default()
+      },
+    );
+    assert_snapshot!(modern_result.unwrap().css);
+  }
+
+  #[test]
+  fn test_disable_replacements() {
+    let input = "* { color: red; } web-view .a, div .b { height: 20rpx; }";
+    let options = ConvertCssOptions {
+      prefix_class: false,
+      units: HashMap::new(),
+      replace_tag: false,
+      tag_map: HashMap::new(),
+      ..ConvertCssOptions::default()
+    };
+    let result = convert_css(input.to_string(), &options);
+    assert_snapshot!(result.unwrap().css);
+  }
+
+  #[test]
+  fn test_rename_placeholders() {
+    let input = "* { color: red; } web-view .a, div .b { height: 20rpx; }";
+    let options = ConvertCssOptions {
+      meta_tag: "data-tag".to_string(),
+      tag_map: HashMap::from([
+        (
+          "*".to_string(),
+          TagReplacement::Unsupported("no-star".to_string()),
+        ),
+        (
+          "web-view".to_string(),
+          TagReplacement::Unsupported("no-web-view".to_string()),
+        ),
+      ]),
+      ..ConvertCssOptions::default()
+    };
+    let result = convert_css(input.to_string(), &options);
+    assert_snapshot!(result.unwrap().css);
+  }
+
+  #[test]
+  fn test_tag_map() {
+    let input = "* { color: red; } view > text, cover-view, web-view .a { height: 20px; }";
+    let options = ConvertCssOptions {
+      tag_map: HashMap::from([
+        ("view".to_string(), TagReplacement::Alias("div".to_string())),
+        (
+          "text".to_string(),
+          TagReplacement::Alias("span".to_string()),
+        ),
+        (
+          "cover-view".to_string(),
+          TagReplacement::unsupported("cover-view"),
+        ),
+      ]),
+      ..ConvertCssOptions::default()
+    };
+    let result = convert_css(input.to_string(), &options).unwrap();
+    assert_snapshot!(result.css);
+
+    let result = convert_css(
+      input.to_string(),
+      &ConvertCssOptions {
+        runtime_tag: true,
+        ..options
+      },
+    )
+    .unwrap();
+    assert_snapshot!(result.css);
+  }
+
+  #[test]
+  fn test_unit_conversion() {
+    let input = indoc! {r#"
+      @media (max-width: 600HD) { .a { width: 10hd; } }
+      .b { height: 20upx; margin: 1rem 2REM; --gap: 0.5rem; width: calc(100% - 2rem); }
+      .c { padding: 10rpx; font-size: 3upx; }
+    "#}
+    .to_string();
+    let options = ConvertCssOptions {
+      units: HashMap::from([
+        (
+          "rpx".to_string(),
+          UnitConversion::Runtime("rpx".to_string()),
+        ),
+        (
+          "upx".to_string(),
+          UnitConversion::Runtime("rpx".to_string()),
+        ),
+        ("hd".to_string(), UnitConversion::Runtime("hd".to_string())),
+        (
+          "rem".to_string(),
+          UnitConversion::Static {
+            factor: 16.0,
+            unit: "px".to_string(),
+          },
+        ),
+      ]),
+      ..ConvertCssOptions::default()
+    };
+    let result = convert_css(input, &options).unwrap();
+    assert_snapshot!(result.css);
+  }
+
+  #[test]
+  fn test_static_rpx() {
+    let input = indoc! {r#"
+      @media (max-width: 750rpx) { .a { width: 375rpx; } }
+      .b { height: calc(100% - 30rpx); --gap: 15rpx; }
+    "#};
+    for conversion in [
+      UnitConversion::rpx_to_vw(750.0),
+      UnitConversion::rpx_to_px(750.0, 375.0),
+    ] {
+      let options = ConvertCssOptions {
+        units: HashMap::from([("rpx".to_string(), conversion)]),
+        ..ConvertCssOptions::default()
+      };
+      let result = convert_css(input.to_string(), &options).unwrap();
+      assert!(!result
+        .css
+        .segments
+        .iter()
+        .any(|segment| matches!(segment, Segment::Unit { .. } | Segment::MediaUnit { .. })));
+      assert_snapshot!(result.css);
+    }
+  }
+
+  #[test]
+  fn test_error_recovery() {
+    let input = indoc! {r#"
+      .a { color: red; }
+      .b { color red; width: 20rpx; }
+      .c:hoverr { color: blue; }
+      .d { height: 10rpx; }
+    "#}
+    .to_string();
+    let options = ConvertCssOptions {
+      error_recovery: true,
+      ..ConvertCssOptions::default()
+    };
+    let result = convert_css(input, &options).unwrap();
+    assert_snapshot!(result.css);
+    let warnings = result
+      .warnings
+      .iter()
+      .map(|w| format!("{}:{} {}", w.line.unwrap(), w.column.unwrap(), w.message))
+      .collect::<Vec<_>>();
+    assert_snapshot!(warnings.join("\n"));
+  }
+
   #[test]
   fn test_throw_error_import() {
     let input = r#"
@@ -518,7 +1282,7 @@ mod tests {
       @import url('./b.css')
     "#
     .to_string();
-    let result = convert_css(input);
+    let result = convert_css(input, &ConvertCssOptions::default());
     match result {
       Ok(_) => panic!("Expected an error, but got Ok"),
       Err(e) => {
@@ -530,7 +1294,7 @@ mod tests {
   #[test]
   fn test_throw_error_input() {
     let input = r#" .a  color: red;}"#.to_string();
-    let result = convert_css(input);
+    let result = convert_css(input, &ConvertCssOptions::default());
     match result {
       Ok(_) => panic!("Expected an error, but got Ok"),
       Err(e) => {
@@ -539,4 +1303,61 @@ mod tests {
       }
     }
   }
+
+  #[test]
+  fn test_class_names() {
+    let input = indoc! {r#"
+      .b .a, .a:not(.c) { color: red; }
+      :host .d { color: blue; }
+      @media (max-width: 100px) { .e.b { color: green; } }
+    "#}
+    .to_string();
+    let result = convert_css(input, &ConvertCssOptions::default()).unwrap();
+    let names = result
+      .classes
+      .iter()
+      .map(|class| class.name.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(names, vec!["a", "b", "c", "d", "e"]);
+    assert_eq!(
+      result.classes[0].scoped,
+      vec![Segment::Prefix, Segment::Text("a".to_string())]
+    );
+  }
+
+  #[test]
+  fn test_hashed_class_names() {
+    let input = ".b .a, .a:hover { color: red; }".to_string();
+    let options = ConvertCssOptions {
+      filename: "src/index.css".to_string(),
+      class_name_pattern: Some("[name]-[local]_[hash]".to_string()),
+      ..ConvertCssOptions::default()
+    };
+    let result = convert_css(input.clone(), &options).unwrap();
+    let hash = &md5_hash(&format!("src/index.css\n{}", input))[..8];
+    assert_eq!(
+      result.classes[0].scoped,
+      vec![Segment::Text(format!("index-a_{}", hash))]
+    );
+    assert!(!result.css.segments.contains(&Segment::Prefix));
+    assert_eq!(
+      result.css.to_string(),
+      format!(".index-b_{hash} .index-a_{hash},.index-a_{hash}:hover{{color:red}}")
+    );
+
+    // 相同的内容, 不同的文件路径生成不同的类名
+    let other = convert_css(
+      input,
+      &ConvertCssOptions {
+        filename: "src/other.css".to_string(),
+        class_name_pattern: Some("[local]_[hash]".to_string()),
+        ..ConvertCssOptions::default()
+      },
+    )
+    .unwrap();
+    assert_ne!(
+      other.classes[0].scoped,
+      vec![Segment::Text(format!("a_{}", hash))]
+    );
+  }
 }
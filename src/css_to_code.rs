@@ -1,86 +1,268 @@
+use crate::diagnostic::{Diagnostic, DiagnosticKind};
+use crate::segment::{CssSegments, ScopedClass, Segment};
+use crate::source_map::offset_to_position;
 use indoc::formatdoc;
-use lazy_regex::{lazy_regex, regex::Captures, Regex};
 use md5::{Digest, Md5};
-use once_cell::sync::Lazy;
-use std::collections::HashMap;
-use std::sync::Mutex;
+use parcel_sourcemap::SourceMap;
+use std::error::Error;
 
 // ---- Core Logic ----
 #[derive(Debug, Clone)]
 pub struct Css2CodeOptions<'css_string> {
-  pub css: &'css_string str,
-  pub host_css: Option<&'css_string str>,
+  pub css: &'css_string CssSegments,
+  pub host_css: Option<&'css_string CssSegments>,
+  pub format: OutputFormat,
+  // 传入时额外导出 classes(options)
+  pub classes: Option<&'css_string [ScopedClass]>,
 }
 
-pub fn css_to_code(options: Css2CodeOptions<'_>) -> String {
-  let imports = Mutex::new(HashMap::new());
+/*
+ * 生成模块的格式, 只影响 import / export 的写法, styleFactory 函数体保持一致
+ * esm:  import I_xxx from "..."; export default function styleFactory
+ * cjs:  var I_xxx = require("..."); module.exports = styleFactory
+ * iife: var <global name> = (function () { ... return styleFactory; })(), 不支持 @import
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+  #[default]
+  Esm,
+  Cjs,
+  Iife(String),
+}
+
+// iife 格式的样式中包含 @import 时返回 InvalidOption 错误
+pub fn css_to_code(options: Css2CodeOptions<'_>) -> Result<String, Diagnostic> {
+  let (code, _) = generate_code(options)?;
+  Ok(code)
+}
+
+/*
+ * 生成代码的同时, 把 css 的 source map 映射到生成的 JS 字符串上
+ * host_css 由 :host 规则拼接后重新解析, 没有原始位置, 不在 source map 中
+ * @return (code, source map json)
+ */
+pub fn css_to_code_with_map(
+  options: Css2CodeOptions<'_>,
+  css_map: &SourceMap,
+) -> Result<(String, String), Diagnostic> {
+  let css = options.css;
+  let (code, segment_starts) = generate_code(options.clone())?;
+  let map = map_code(&code, css, &segment_starts, css_map).map_err(|e| {
+    Diagnostic::new(DiagnosticKind::Serialize, e.to_string()).context("Source map error")
+  })?;
+  Ok((code, map))
+}
+
+// css 的 source map => 生成代码的 source map json
+fn map_code(
+  code: &str,
+  css: &CssSegments,
+  segment_starts: &[usize],
+  css_map: &SourceMap,
+) -> Result<String, Box<dyn Error>> {
+  let css_start = code
+    .find(CSS_CODE_PREFIX)
+    .map(|index| index + CSS_CODE_PREFIX.len())
+    .ok_or("Cannot locate css in generated code")?;
+
+  let mut map = SourceMap::new("/");
+  for (index, source) in css_map.get_sources().iter().enumerate() {
+    map.add_source(source);
+    if let Ok(content) = css_map.get_source_content(index as u32) {
+      map.set_source_content(index, content)?;
+    }
+  }
+
+  for mapping in css_map.get_mappings() {
+    let Some((index, inner)) = css.locate(mapping.generated_line, mapping.generated_column) else {
+      continue;
+    };
+    // 静态文本经过转义, 占位符片段统一指向片段开头
+    let inner_offset = match &css.segments[index] {
+      Segment::Text(text) => text.get(..inner).map_or(0, |text| json_escape(text).len()),
+      _ => 0,
+    };
+    let offset = css_start + segment_starts[index] + inner_offset;
+    let (line, column) = offset_to_position(code, offset);
+    map.add_mapping(line, column, mapping.original);
+  }
+
+  Ok(map.to_json(None)?)
+}
+
+// @return (code, css 中每个片段在 JS 字符串中的起始位置)
+fn generate_code(options: Css2CodeOptions<'_>) -> Result<(String, Vec<usize>), Diagnostic> {
+  let mut imports = Vec::new();
+  let mut unit_functions = Vec::new();
 
   // Process main CSS
-  let css_code = process_text(options.css, Some(&imports));
+  let (css_code, segment_starts) = emit_segments(options.css, &mut imports, &mut unit_functions);
 
   // Process Host CSS
-  let host_css_code = options
-    .host_css
-    .map_or_else(String::new, |hc| process_text(hc, None));
+  let host_css_code = options.host_css.map_or_else(String::new, |hc| {
+    emit_segments(hc, &mut imports, &mut unit_functions).0
+  });
+
+  let code = generate_output(
+    &css_code,
+    &host_css_code,
+    &imports,
+    &unit_functions,
+    &options.format,
+    options.classes,
+  )?;
+  Ok((code, segment_starts))
+}
 
-  generate_output(&css_code, &host_css_code, &imports.into_inner().unwrap())
+/*
+ * 生成模块的类型声明, 描述 styleFactory(options) 的参数约定
+ * @param unit_functions: 运行时单位转换函数, 不包含 rpx 时 rpx 为可选参数
+ */
+pub fn generate_dts(
+  format: &OutputFormat,
+  classes: Option<&[ScopedClass]>,
+  unit_functions: &[String],
+) -> String {
+  // esm 直接导出, cjs / iife 声明在与 styleFactory 同名的 namespace 中
+  let export = match format {
+    OutputFormat::Esm => "export ",
+    OutputFormat::Cjs | OutputFormat::Iife(_) => "",
+  };
+  let mut declarations = formatdoc! {r#"
+    {export}interface StyleFactoryOptions {{
+      /** 拼接在 class 名前的前缀 */
+      prefix?: string;
+      /** 标签名转换 */
+      tag?: (tag: string) => string;
+      /** rpx 转换为 px */
+      rpx{rpx_optional}: (value: number) => number;{unit_fields}
+      /** :host 选择器替换值, 默认 'host-placeholder' */
+      host?: string;
+      /** 传入时接收 :host 样式, 否则 :host 样式拼接在返回值之前 */
+      hostStyle?: (css: string) => void;
+    }}"#, export = export,
+    rpx_optional = if unit_functions.iter().any(|func| func == "rpx") { "" } else { "?" },
+    unit_fields = unit_functions
+      .iter()
+      .filter(|func| func.as_str() != "rpx")
+      .map(|func| format!("\n  /** {func} 转换为 px */\n  {func}: (value: number) => number;"))
+      .collect::<String>()
+  };
+  if let Some(classes) = classes {
+    let fields = classes
+      .iter()
+      .map(|class| format!(r#""{}": string;"#, json_escape(&class.name)))
+      .collect::<Vec<_>>()
+      .join(" ");
+    declarations.push_str(&format!(
+      "\n{export}function classes(options?: Pick<StyleFactoryOptions, \"prefix\">): {{ {fields} }};"
+    ));
+  }
+
+  match format {
+    OutputFormat::Esm => formatdoc! {r#"
+      {declarations}
+      export default function styleFactory(options: StyleFactoryOptions): string;
+    "#, declarations = declarations},
+    OutputFormat::Cjs => formatdoc! {r#"
+      declare function styleFactory(options: styleFactory.StyleFactoryOptions): string;
+      declare namespace styleFactory {{
+      {declarations}
+      }}
+      export = styleFactory;
+    "#, declarations = indent(&declarations)},
+    OutputFormat::Iife(global_name) => formatdoc! {r#"
+      declare function {global_name}(options: {global_name}.StyleFactoryOptions): string;
+      declare namespace {global_name} {{
+      {declarations}
+      }}
+    "#, global_name = global_name, declarations = indent(&declarations)},
+  }
 }
 
 // ---- Private Helper Functions ----
-fn md5_hash(input: &str) -> String {
+pub(crate) fn md5_hash(input: &str) -> String {
   let mut hasher = Md5::new();
   hasher.update(input.as_bytes());
   format!("{:x}", hasher.finalize())
 }
 
+// 每行缩进两个空格, 空行保持为空
+fn indent(text: &str) -> String {
+  text
+    .lines()
+    .map(|line| match line {
+      "" => String::new(),
+      line => format!("  {line}"),
+    })
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
 fn json_escape(s: &str) -> String {
   s.replace('\\', r"\\")
     .replace('"', r#"\""#)
     .replace('\n', r"\n")
 }
 
-static PREFIX_REGEX: Lazy<Regex> = lazy_regex!(r"__PREFIX__");
-static HOST_REGEX: Lazy<Regex> = lazy_regex!(r"__HOST__");
-static RPX_REGEX: Lazy<Regex> = lazy_regex!(r#"\\"__RPX__\(([^)]+)\)\\""#);
-static IMPORT_REGEX: Lazy<Regex> = lazy_regex!(r#"\@import-style \(\\"([^\)]+)\\"\);"#);
-
-fn process_text(text: &str, imports: Option<&Mutex<HashMap<String, String>>>) -> String {
-  let mut result = json_escape(text);
-
-  result = PREFIX_REGEX
-    .replace_all(&result, r#"" , prefix , ""#)
-    .into_owned();
-  result = HOST_REGEX
-    .replace_all(&result, r#"'" , host , "'"#)
-    .into_owned();
-  result = RPX_REGEX
-    .replace_all(&result, |caps: &Captures<'_>| {
-      format!(r#"" , rpx({}) , "px"#, &caps[1])
-    })
-    .into_owned();
+/*
+ * 把片段拼接成 JS 字符串数组的内容
+ * @param imports: 收集 @import 的 (url, 函数名), 按出现顺序去重
+ * @param unit_functions: 收集用到的单位转换函数, 按出现顺序去重
+ */
+fn emit_segments(
+  css: &CssSegments,
+  imports: &mut Vec<(String, String)>,
+  unit_functions: &mut Vec<String>,
+) -> (String, Vec<usize>) {
+  let mut code = String::new();
+  let mut starts = Vec::with_capacity(css.segments.len());
 
-  if let Some(imports_map) = imports {
-    result = IMPORT_REGEX
-      .replace_all(&result, |caps: &Captures<'_>| {
-        let url = caps[1].into();
+  for segment in &css.segments {
+    starts.push(code.len());
+    match segment {
+      Segment::Text(text) => code.push_str(&json_escape(text)),
+      Segment::Prefix => code.push_str(r#"" , prefix , ""#),
+      Segment::Host => code.push_str(r#"'" , host , "'"#),
+      Segment::Unit { func, value } | Segment::MediaUnit { func, value } => {
+        if !unit_functions.contains(func) {
+          unit_functions.push(func.clone());
+        }
+        code.push_str(&format!(r#"" , {}({}) , "px"#, func, value))
+      }
+      Segment::Import(url) => {
         let fn_name = format!("I_{}", md5_hash(url));
-        imports_map
-          .lock()
-          .unwrap()
-          .insert(url.to_string(), fn_name.clone());
-        format!(r#"" , {}(options) , ""#, fn_name)
-      })
-      .into_owned();
+        if !imports.iter().any(|(import_url, _)| import_url == url) {
+          imports.push((url.clone(), fn_name.clone()));
+        }
+        code.push_str(&format!(r#"" , {}(options) , ""#, fn_name));
+      }
+      Segment::Tag(name) => code.push_str(&format!(r#"" , tag("{}") , ""#, json_escape(name))),
+    }
   }
 
-  result
+  (code, starts)
 }
 
+// 生成代码中 css 字符串的起始位置
+const CSS_CODE_PREFIX: &str = r#"var css = [""#;
+
 fn generate_output(
   css_code: &str,
   host_css_code: &str,
-  imports: &HashMap<String, String>,
-) -> String {
+  imports: &[(String, String)],
+  unit_functions: &[String],
+  format: &OutputFormat,
+  classes: Option<&[ScopedClass]>,
+) -> Result<String, Diagnostic> {
+  // 通过 <script> 或 new Function 加载时没有 require
+  if matches!(format, OutputFormat::Iife(_)) && !imports.is_empty() {
+    return Err(Diagnostic::new(
+      DiagnosticKind::InvalidOption,
+      "format `iife` does not support @import, use `esm` or `cjs` instead",
+    ));
+  }
+
   let host_code = if !host_css_code.is_empty() {
     formatdoc! {r#"
       var hostStyleText = ["{host_css_code}", ""].join("");
@@ -98,25 +280,91 @@ fn generate_output(
 
   let import_code = imports
     .iter()
-    .map(|(url, fn_name)| format!(r#"import {fn_name} from "{url}";"#))
+    .map(|(url, fn_name)| (json_escape(url), fn_name))
+    .map(|(url, fn_name)| match format {
+      OutputFormat::Esm => format!(r#"import {fn_name} from "{url}";"#),
+      // iife 中的 @import 已在上面报错
+      OutputFormat::Cjs | OutputFormat::Iife(_) => {
+        format!(r#"var {fn_name} = require("{url}");"#)
+      }
+    })
     .collect::<Vec<_>>()
     .join("\n");
 
-  formatdoc! {r#"
-    {import_code}
-    export default function styleFactory(options) {{
+  // rpx 之外的单位转换函数同样从 options 中读取
+  let unit_code = unit_functions
+    .iter()
+    .filter(|func| func.as_str() != "rpx")
+    .map(|func| format!("\n  var {func} = options.{func};"))
+    .collect::<String>();
+
+  let factory_code = formatdoc! {r#"
+    function styleFactory(options) {{
       var prefix = options.prefix || '';
       var tag = options.tag || function (tag) {{ return tag; }};
-      var rpx = options.rpx;
+      var rpx = options.rpx;{unit_code}
       var host = options.host || 'host-placeholder';
       var css = ["{css_code}", ""].join("");
       {host_code}
       return css;
-    }}
-  "#, import_code = import_code, css_code = css_code, host_code = host_code
-  }
-  .trim()
-  .into()
+    }}"#, css_code = css_code, host_code = host_code, unit_code = unit_code
+  };
+
+  // classes(options) 返回 原始类名 => 运行时类名
+  let classes_code = classes.map(|classes| {
+    let fields = classes
+      .iter()
+      .map(|class| {
+        let value = class
+          .scoped
+          .iter()
+          .map(|segment| match segment {
+            Segment::Prefix => "prefix".to_string(),
+            segment => format!(r#""{}""#, json_escape(&segment.to_string())),
+          })
+          .collect::<Vec<_>>()
+          .join(" + ");
+        format!(r#"  "{}": {},"#, json_escape(&class.name), value)
+      })
+      .collect::<Vec<_>>()
+      .join("\n");
+    formatdoc! {r#"
+      function classes(options) {{
+        var prefix = (options && options.prefix) || '';
+        return {{
+      {fields}
+        }};
+      }}"#, fields = indent(&fields)
+    }
+  });
+
+  let code = match (format, classes_code) {
+    (OutputFormat::Esm, None) => format!("{import_code}\nexport default {factory_code}"),
+    (OutputFormat::Esm, Some(classes_code)) => {
+      format!("{import_code}\nexport default {factory_code}\nexport {classes_code}")
+    }
+    (OutputFormat::Cjs, classes_code) => {
+      let exports = match classes_code {
+        Some(classes_code) => format!(
+          "{classes_code}\nmodule.exports = styleFactory;\nmodule.exports.classes = classes;"
+        ),
+        None => "module.exports = styleFactory;".to_string(),
+      };
+      format!("{import_code}\n{factory_code}\n{exports}")
+    }
+    (OutputFormat::Iife(global_name), classes_code) => {
+      let exports = match classes_code {
+        Some(classes_code) => {
+          format!("{classes_code}\nstyleFactory.classes = classes;\nreturn styleFactory;")
+        }
+        None => "return styleFactory;".to_string(),
+      };
+      let body = indent(format!("{import_code}\n{factory_code}\n{exports}").trim());
+      format!("var {global_name} = (function () {{\n{body}\n}})();")
+    }
+  };
+
+  Ok(code.trim().into())
 }
 
 // ---- 测试用例 ----
@@ -125,52 +373,220 @@ mod tests {
   use super::*;
   use insta::assert_snapshot;
 
+  fn text(text: &str) -> Segment {
+    Segment::Text(text.to_string())
+  }
+
+  fn rpx(value: &str) -> Segment {
+    Segment::Unit {
+      func: "rpx".to_string(),
+      value: value.to_string(),
+    }
+  }
+
+  fn code(css: Vec<Segment>, host_css: Option<Vec<Segment>>, format: OutputFormat) -> String {
+    let css = CssSegments::from(css);
+    let host_css = host_css.map(CssSegments::from);
+    css_to_code(Css2CodeOptions {
+      css: &css,
+      host_css: host_css.as_ref(),
+      format,
+      classes: None,
+    })
+    .unwrap()
+  }
+
   #[test]
   fn test_keep_is_prefix() {
-    let css = r#".__PREFIX__h5-blockquote:not(:is(:lang(ae),:lang(yi))){margin-left:"__RPX__(40)";margin-right:"__RPX__(40)"}"#;
-    let options = Css2CodeOptions {
-      css,
-      host_css: None,
-    };
-    let output = css_to_code(options);
+    let css = vec![
+      text("."),
+      Segment::Prefix,
+      text("h5-blockquote:not(:is(:lang(ae),:lang(yi))){margin-left:"),
+      rpx("40"),
+      text(";margin-right:"),
+      rpx("40"),
+      text("}"),
+    ];
+    let output = code(css, None, OutputFormat::Esm);
 
     assert_snapshot!(output.trim());
   }
 
   #[test]
   fn test_tow_prefix() {
-    let css = r#".__PREFIX__a{width:"__RPX__(100)"}.__PREFIX__b{height:"__RPX__(50)"}"#;
-    let host_css = r#"[is=__HOST__]{color:#000;width:"__RPX__(100)";height:"__RPX__(20)"}"#;
-    let options = Css2CodeOptions {
-      css,
-      host_css: Some(host_css),
-    };
-    let output = css_to_code(options);
+    let css = vec![
+      text("."),
+      Segment::Prefix,
+      text("a{width:"),
+      rpx("100"),
+      text("}."),
+      Segment::Prefix,
+      text("b{height:"),
+      rpx("50"),
+      text("}"),
+    ];
+    let host_css = vec![
+      text("[is="),
+      Segment::Host,
+      text("]{color:#000;width:"),
+      rpx("100"),
+      text(";height:"),
+      rpx("20"),
+      text("}"),
+    ];
+    let output = code(css, Some(host_css), OutputFormat::Esm);
 
     assert_snapshot!(output.trim());
   }
 
   #[test]
   fn test_host_css() {
-    let input = r#"[is=__HOST__]{color:#fff}"#;
-    let options = Css2CodeOptions {
-      css: input,
-      host_css: None,
-    };
-    let output = css_to_code(options);
+    let css = vec![text("[is="), Segment::Host, text("]{color:#fff}")];
+    let output = code(css, None, OutputFormat::Esm);
 
     assert_snapshot!(output.trim());
   }
 
   #[test]
   fn test_import_style() {
-    let input = r#"@import-style ("./a.css");"#;
-    let options = Css2CodeOptions {
-      css: input,
-      host_css: None,
-    };
-    let output = css_to_code(options);
+    let css = vec![Segment::Import("./a.css".to_string())];
+    let output = code(css, None, OutputFormat::Esm);
+
+    assert_snapshot!(output.trim());
+  }
+
+  #[test]
+  fn test_media_rpx() {
+    let css = vec![
+      text("@media (max-width:"),
+      Segment::MediaUnit {
+        func: "rpx".to_string(),
+        value: "600".to_string(),
+      },
+      text("){."),
+      Segment::Prefix,
+      text("a{width:"),
+      rpx("24"),
+      text("}}"),
+    ];
+    let output = code(css, None, OutputFormat::Esm);
 
     assert_snapshot!(output.trim());
   }
+
+  #[test]
+  fn test_placeholder_like_content() {
+    // 内容中出现旧的占位符文本时原样输出
+    let css = vec![
+      Segment::Import("./__RPX__(1).css".to_string()),
+      text(r#".__PREFIX__a:after{content:"__RPX__(10) __HOST__"}"#),
+    ];
+    let output = code(css, None, OutputFormat::Esm);
+
+    assert_snapshot!(output.trim());
+  }
+
+  #[test]
+  fn test_output_format() {
+    let css = vec![
+      Segment::Import("./a.css".to_string()),
+      text("."),
+      Segment::Prefix,
+      text("a{width:"),
+      rpx("100"),
+      text("}"),
+    ];
+    let host_css = vec![text("[is="), Segment::Host, text("]{color:#000}")];
+    for format in [
+      OutputFormat::Cjs,
+      OutputFormat::Iife("appStyle".to_string()),
+    ] {
+      // iife 格式不支持 @import
+      let css = match format {
+        OutputFormat::Iife(_) => css[1..].to_vec(),
+        _ => css.clone(),
+      };
+      let output = code(css, Some(host_css.clone()), format);
+      assert_snapshot!(output);
+    }
+
+    let css = CssSegments::from(css);
+    let error = css_to_code(Css2CodeOptions {
+      css: &css,
+      host_css: None,
+      format: OutputFormat::Iife("appStyle".to_string()),
+      classes: None,
+    })
+    .unwrap_err();
+    assert_eq!(error.kind, DiagnosticKind::InvalidOption);
+  }
+
+  #[test]
+  fn test_generate_dts() {
+    let rpx = ["rpx".to_string()];
+    assert_snapshot!(generate_dts(&OutputFormat::Esm, None, &rpx));
+    assert_snapshot!(generate_dts(&OutputFormat::Cjs, None, &rpx));
+    assert_snapshot!(generate_dts(
+      &OutputFormat::Iife("appStyle".to_string()),
+      None,
+      &rpx
+    ));
+  }
+
+  #[test]
+  fn test_unit_functions() {
+    let css = vec![
+      text("@media (max-width:"),
+      Segment::MediaUnit {
+        func: "hd".to_string(),
+        value: "600".to_string(),
+      },
+      text("){.a{width:"),
+      Segment::Unit {
+        func: "hd".to_string(),
+        value: "24".to_string(),
+      },
+      text(";height:"),
+      rpx("10"),
+      text("}}"),
+    ];
+    assert_snapshot!(code(css, None, OutputFormat::Esm));
+    assert_snapshot!(generate_dts(
+      &OutputFormat::Esm,
+      None,
+      &["rpx".to_string(), "hd".to_string()]
+    ));
+  }
+
+  #[test]
+  fn test_classes_export() {
+    let css = CssSegments::from(vec![
+      text("."),
+      Segment::Prefix,
+      text("a{}."),
+      Segment::Prefix,
+      text("b-c{}"),
+    ]);
+    let classes = vec![
+      ScopedClass {
+        name: "a".to_string(),
+        scoped: vec![Segment::Prefix, text("a")],
+      },
+      ScopedClass {
+        name: "b-c".to_string(),
+        scoped: vec![Segment::Prefix, text("b-c")],
+      },
+    ];
+    for format in [OutputFormat::Esm, OutputFormat::Cjs] {
+      let output = css_to_code(Css2CodeOptions {
+        css: &css,
+        host_css: None,
+        format: format.clone(),
+        classes: Some(&classes),
+      })
+      .unwrap();
+      assert_snapshot!(output);
+      assert_snapshot!(generate_dts(&format, Some(&classes), &["rpx".to_string()]));
+    }
+  }
 }
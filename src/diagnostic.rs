@@ -0,0 +1,186 @@
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+  Parse,
+  Visit,
+  Minify,
+  Serialize,
+  Bundle,
+  Resolve,
+  // 选项的值无效, 或与样式内容不兼容
+  InvalidOption,
+}
+
+impl DiagnosticKind {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      DiagnosticKind::Parse => "parse",
+      DiagnosticKind::Visit => "visit",
+      DiagnosticKind::Minify => "minify",
+      DiagnosticKind::Serialize => "serialize",
+      DiagnosticKind::Bundle => "bundle",
+      DiagnosticKind::Resolve => "resolve",
+      DiagnosticKind::InvalidOption => "invalid-option",
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+  Error,
+  Warning,
+}
+
+impl Severity {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Severity::Error => "error",
+      Severity::Warning => "warning",
+    }
+  }
+}
+
+/*
+ * 带位置信息的错误, line 和 column 都从 1 开始
+ * Display 输出与之前拼接的错误字符串保持一致
+ */
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+  pub kind: DiagnosticKind,
+  pub severity: Severity,
+  pub message: String,
+  pub file: Option<String>,
+  pub line: Option<u32>,
+  pub column: Option<u32>,
+  pub code_frame: Option<String>,
+  reason: String,
+}
+
+impl Diagnostic {
+  pub fn new<S: Into<String>>(kind: DiagnosticKind, message: S) -> Self {
+    let message = message.into();
+    Diagnostic {
+      kind,
+      severity: Severity::Error,
+      reason: message.clone(),
+      message,
+      file: None,
+      line: None,
+      column: None,
+      code_frame: None,
+    }
+  }
+
+  pub fn from_css_error<T: fmt::Display>(
+    kind: DiagnosticKind,
+    error: &lightningcss::error::Error<T>,
+  ) -> Self {
+    let mut diagnostic = Diagnostic::new(kind, error.kind.to_string());
+    diagnostic.reason = error.to_string();
+    if let Some(loc) = &error.loc {
+      diagnostic.file = Some(loc.filename.clone()).filter(|file| !file.is_empty());
+      // lightningcss 的 line 从 0 开始, column 从 1 开始
+      diagnostic.line = Some(loc.line + 1);
+      diagnostic.column = Some(loc.column);
+    }
+    diagnostic
+  }
+
+  // error_recovery 模式下收集的非致命错误
+  pub fn warning(mut self) -> Self {
+    self.severity = Severity::Warning;
+    self
+  }
+
+  // 在错误信息前添加上下文, 例如 "Parse error: ..."
+  pub fn context(mut self, prefix: &str) -> Self {
+    self.reason = format!("{}: {}", prefix, self.reason);
+    self
+  }
+
+  pub fn with_code_frame(mut self, source: &str) -> Self {
+    if let (Some(line), Some(column)) = (self.line, self.column) {
+      self.code_frame = code_frame(source, line, column);
+    }
+    self
+  }
+}
+
+impl fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.reason)
+  }
+}
+
+impl Error for Diagnostic {}
+
+/*
+ * 生成错误位置附近的代码片段
+ *   1 | .a {
+ * > 2 |   color red;
+ *     |   ^
+ *   3 | }
+ */
+fn code_frame(source: &str, line: u32, column: u32) -> Option<String> {
+  const CONTEXT_LINES: usize = 2;
+
+  // 使用 split 保留末尾的空行, 文件结尾处的错误也能生成代码片段
+  let lines = source
+    .split('\n')
+    .map(|line| line.trim_end_matches('\r'))
+    .collect::<Vec<_>>();
+  let line_index = (line as usize).checked_sub(1)?;
+  if line_index >= lines.len() {
+    return None;
+  }
+
+  let start = line_index.saturating_sub(CONTEXT_LINES);
+  let end = (line_index + CONTEXT_LINES + 1).min(lines.len());
+  let gutter_width = end.to_string().len();
+
+  let mut frame = Vec::new();
+  for (index, text) in lines.iter().enumerate().take(end).skip(start) {
+    let marker = if index == line_index { ">" } else { " " };
+    frame.push(
+      format!(
+        "{} {:>width$} | {}",
+        marker,
+        index + 1,
+        text,
+        width = gutter_width
+      )
+      .trim_end()
+      .to_string(),
+    );
+    if index == line_index {
+      let padding = text
+        .chars()
+        .take(column.saturating_sub(1) as usize)
+        .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+      frame.push(format!(
+        "  {:>width$} | {}^",
+        "",
+        padding,
+        width = gutter_width
+      ));
+    }
+  }
+
+  Some(frame.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use insta::assert_snapshot;
+
+  #[test]
+  fn test_code_frame() {
+    let source = ".a {\n  color: red;\n}\n.b color: blue }\n.c {}\n.d {}\n.e {}";
+    assert_snapshot!(code_frame(source, 4, 4).unwrap());
+    assert!(code_frame(source, 10, 1).is_none());
+  }
+}
@@ -0,0 +1,43 @@
+use crate::assets::Asset;
+use napi::{Env, JsObject};
+
+#[napi(object)]
+#[derive(Debug)]
+pub struct JSAsset {
+  pub url: String,
+  pub path: Option<String>,
+  pub file: String,
+  pub line: u32,
+  pub column: u32,
+}
+
+impl From<&Asset> for JSAsset {
+  fn from(asset: &Asset) -> Self {
+    JSAsset {
+      url: asset.url.clone(),
+      path: asset
+        .path
+        .as_ref()
+        .map(|path| path.to_string_lossy().to_string()),
+      file: asset.file.clone(),
+      line: asset.line,
+      column: asset.column,
+    }
+  }
+}
+
+pub fn to_js_assets(assets: &[Asset]) -> Vec<JSAsset> {
+  assets.iter().map(JSAsset::from).collect()
+}
+
+// rewriteUrl 回调的参数
+pub fn create_asset_object(env: &Env, asset: &Asset) -> napi::Result<JsObject> {
+  let asset = JSAsset::from(asset);
+  let mut object = env.create_object()?;
+  object.set_named_property("url", asset.url)?;
+  object.set_named_property("path", asset.path)?;
+  object.set_named_property("file", asset.file)?;
+  object.set_named_property("line", asset.line)?;
+  object.set_named_property("column", asset.column)?;
+  Ok(object)
+}
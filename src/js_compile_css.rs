@@ -1,4 +1,17 @@
-use crate::compile_css::compile_css;
+use crate::assets::AssetOptions;
+use crate::compile_css::{compile_css, CompileCssOptions, CompileResult};
+use crate::diagnostic::Diagnostic;
+use crate::js_asset::{to_js_assets, JSAsset};
+use crate::js_diagnostic::{to_js_warnings, to_napi_error, JSDiagnostic};
+use crate::js_source_hooks::{with_js_hooks, JsHookFunctions, JsHookRefs, JsHooks};
+use crate::js_targets::{to_targets, JSTargets};
+use crate::memory_provider::MemoryFiles;
+use crate::resolver::Resolver;
+use lazy_regex::Regex;
+use napi::bindgen_prelude::AsyncTask;
+use napi::{Env, JsFunction, Task};
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::{collections::HashMap, path::Path};
 
 #[napi(object)]
@@ -6,15 +19,112 @@ pub struct JSCompileResult {
   pub css: String,
   pub dependencies: Vec<String>,
   pub imports: HashMap<String, Vec<String>>,
+  pub warnings: Vec<JSDiagnostic>,
+  pub externals: Vec<String>,
+  pub assets: Vec<JSAsset>,
 }
 
-#[napi(js_name = "compileCSS")]
-pub fn js_compile_css(entry: String) -> Result<JSCompileResult, napi::Error> {
-  let entry = Path::new(&entry);
-  let result = compile_css(entry);
+#[napi(object)]
+#[derive(Default)]
+pub struct JSCompileCssOptions {
+  pub targets: Option<JSTargets>,
+  pub error_recovery: Option<bool>,
+  // { "@": "/project/src" } => @import "@/styles/vars.css"
+  pub alias: Option<HashMap<String, String>>,
+  pub module_directories: Option<Vec<String>>,
+  pub extensions: Option<Vec<String>>,
+  pub main_fields: Option<Vec<String>>,
+  // 文件路径 => 源码, 优先于磁盘上的文件, entry 也可以只存在于内存中
+  pub files: Option<HashMap<String, String>>,
+  // 内存中不存在的文件是否从磁盘读取, 默认为 true
+  pub read_from_disk: Option<bool>,
+  // 返回 null / undefined 时使用默认的实现, 需要同步返回
+  #[napi(ts_type = "(path: string) => string | null | undefined")]
+  pub read: Option<JsFunction>,
+  #[napi(ts_type = "(specifier: string, originatingFile: string) => string | null | undefined")]
+  pub resolve: Option<JsFunction>,
+  // 正则, 匹配的 @import 保留在输出的顶部不打包, 远程地址总是外部引入
+  pub externals: Option<Vec<String>>,
+  // url() 中的相对地址改写为 publicPath + 相对于 entry 所在目录的路径, 目录外的资源保持原地址并返回警告
+  pub public_path: Option<String>,
+  // 优先于 publicPath, 返回 null / undefined 时保持原地址
+  #[napi(ts_type = "(asset: JsAsset) => string | null | undefined")]
+  pub rewrite_url: Option<JsFunction>,
+  // 小于该字节数的 url() 资源内联为 base64 的 data: 地址, 优先于 rewriteUrl 和 publicPath
+  pub inline_limit: Option<u32>,
+}
+
+impl JSCompileCssOptions {
+  pub(crate) fn to_compile_options(&self) -> Result<CompileCssOptions, napi::Error> {
+    Ok(CompileCssOptions {
+      targets: to_targets(self.targets.as_ref())?,
+      error_recovery: self.error_recovery.unwrap_or(false),
+      resolver: self.to_resolver(),
+      externals: self.to_externals()?,
+      assets: AssetOptions {
+        public_path: self.public_path.clone(),
+        ..AssetOptions::default()
+      },
+      files: self.files.as_ref().map(|files| {
+        Arc::new(MemoryFiles::new(
+          files
+            .iter()
+            .map(|(path, source)| (PathBuf::from(path), source.clone())),
+          self.read_from_disk.unwrap_or(true),
+        ))
+      }),
+      inline_limit: self.inline_limit.map(|limit| limit as usize),
+      ..CompileCssOptions::default()
+    })
+  }
 
-  match result {
-    Ok(result) => Ok(JSCompileResult {
+  pub(crate) fn hook_functions(&self) -> Option<JsHookFunctions<'_>> {
+    let functions = JsHookFunctions {
+      read: self.read.as_ref(),
+      resolve: self.resolve.as_ref(),
+      rewrite_url: self.rewrite_url.as_ref(),
+    };
+    (!functions.is_empty()).then_some(functions)
+  }
+
+  fn to_externals(&self) -> Result<Vec<Regex>, napi::Error> {
+    self
+      .externals
+      .iter()
+      .flatten()
+      .map(|pattern| {
+        Regex::new(pattern).map_err(|e| {
+          napi::Error::new(
+            napi::Status::InvalidArg,
+            format!("Invalid externals pattern `{}`: {}", pattern, e),
+          )
+        })
+      })
+      .collect()
+  }
+
+  fn to_resolver(&self) -> Resolver {
+    let default = Resolver::default();
+    Resolver {
+      alias: self
+        .alias
+        .iter()
+        .flatten()
+        .map(|(name, target)| (name.clone(), PathBuf::from(target)))
+        .collect(),
+      module_directories: self
+        .module_directories
+        .clone()
+        .unwrap_or(default.module_directories),
+      extensions: self.extensions.clone().unwrap_or(default.extensions),
+      main_fields: self.main_fields.clone().unwrap_or(default.main_fields),
+    }
+  }
+}
+
+impl From<CompileResult> for JSCompileResult {
+  fn from(result: CompileResult) -> Self {
+    JSCompileResult {
       css: result.css,
       dependencies: result
         .dependencies
@@ -31,14 +141,108 @@ pub fn js_compile_css(entry: String) -> Result<JSCompileResult, napi::Error> {
           )
         })
         .collect(),
+      warnings: to_js_warnings(&result.warnings),
+      externals: result.externals,
+      assets: to_js_assets(&result.assets),
+    }
+  }
+}
+
+#[napi(js_name = "compileCSS")]
+pub fn js_compile_css(
+  env: Env,
+  entry: String,
+  options: Option<JSCompileCssOptions>,
+) -> Result<JSCompileResult, napi::Error> {
+  let options = options.unwrap_or_default();
+  let compile_options = options.to_compile_options()?;
+
+  let entry = Path::new(&entry);
+  let result = match options.hook_functions() {
+    Some(functions) => with_js_hooks(env, functions, |hooks| {
+      compile_css(entry, &with_hooks(compile_options, hooks))
     }),
-    Err(err) => Err(napi::Error::new(
-      napi::Status::GenericFailure,
-      format!("Error: {}", err),
-    )),
+    None => compile_css(entry, &compile_options),
+  };
+  result
+    .map(JSCompileResult::from)
+    .map_err(|e| to_napi_error(env, &e.context("Error")))
+}
+
+pub(crate) fn with_hooks(options: CompileCssOptions, hooks: JsHooks) -> CompileCssOptions {
+  CompileCssOptions {
+    hooks: hooks.source,
+    assets: AssetOptions {
+      rewrite: hooks.rewrite_url,
+      ..options.assets
+    },
+    ..options
   }
 }
 
+pub struct CompileCssTask {
+  entry: PathBuf,
+  options: CompileCssOptions,
+  diagnostic: Option<Diagnostic>,
+  hook_refs: Option<JsHookRefs>,
+}
+
+impl Task for CompileCssTask {
+  type Output = CompileResult;
+  type JsValue = JSCompileResult;
+
+  fn compute(&mut self) -> napi::Result<Self::Output> {
+    compile_css(&self.entry, &self.options).map_err(|e| {
+      let e = e.context("Error");
+      let error = napi::Error::from_reason(e.to_string());
+      self.diagnostic = Some(e);
+      error
+    })
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+    Ok(JSCompileResult::from(output))
+  }
+
+  fn reject(&mut self, env: Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+    match self.diagnostic.take() {
+      Some(diagnostic) => Err(to_napi_error(env, &diagnostic)),
+      None => Err(err),
+    }
+  }
+
+  fn finally(&mut self, env: Env) -> napi::Result<()> {
+    match self.hook_refs.take() {
+      Some(hook_refs) => hook_refs.release(env),
+      None => Ok(()),
+    }
+  }
+}
+
+#[napi(js_name = "compileCSSAsync")]
+pub fn js_compile_css_async(
+  env: Env,
+  entry: String,
+  options: Option<JSCompileCssOptions>,
+) -> Result<AsyncTask<CompileCssTask>, napi::Error> {
+  let options = options.unwrap_or_default();
+  let mut compile_options = options.to_compile_options()?;
+  let hook_refs = match options.hook_functions() {
+    Some(functions) => {
+      let (hook_refs, hooks) = JsHookRefs::new(env, functions)?;
+      compile_options = with_hooks(compile_options, hooks);
+      Some(hook_refs)
+    }
+    None => None,
+  };
+  Ok(AsyncTask::new(CompileCssTask {
+    entry: PathBuf::from(entry),
+    options: compile_options,
+    diagnostic: None,
+    hook_refs,
+  }))
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -64,7 +268,7 @@ mod tests {
     let foo = dir.path().join("foo.css");
     fs::write(&foo, "p { color: blue; }")?;
 
-    let result = js_compile_css(entry.to_string_lossy().to_string()).unwrap();
+    let result = JSCompileResult::from(compile_css(&entry, &CompileCssOptions::default())?);
     let dependencies = result.dependencies;
     assert_eq!(dependencies.len(), 2);
     assert_snapshot!(result.css);
@@ -87,4 +291,26 @@ mod tests {
 
     Ok(())
   }
+
+  #[test]
+  fn test_compile_css_task() {
+    let dir = tempdir().unwrap();
+    let entry = dir.path().join("entry.css");
+    fs::write(&entry, "@import 'missing.css';\n.a { color: red; }").unwrap();
+
+    let mut task = CompileCssTask {
+      entry: entry.clone(),
+      options: CompileCssOptions::default(),
+      diagnostic: None,
+      hook_refs: None,
+    };
+    let error = task.compute().unwrap_err();
+    let diagnostic = task.diagnostic.unwrap();
+    // 与同步版本的错误信息保持一致
+    let expected = compile_css(&entry, &CompileCssOptions::default())
+      .unwrap_err()
+      .context("Error");
+    assert_eq!(error.reason, expected.to_string());
+    assert_eq!(diagnostic.kind, expected.kind);
+  }
 }
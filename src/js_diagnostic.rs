@@ -0,0 +1,52 @@
+use crate::diagnostic::{Diagnostic, DiagnosticKind};
+use napi::{Env, JsError};
+
+#[napi(object)]
+#[derive(Debug)]
+pub struct JSDiagnostic {
+  pub kind: String,
+  pub severity: String,
+  pub message: String,
+  pub file: Option<String>,
+  pub line: Option<u32>,
+  pub column: Option<u32>,
+  pub code_frame: Option<String>,
+}
+
+impl From<&Diagnostic> for JSDiagnostic {
+  fn from(diagnostic: &Diagnostic) -> Self {
+    JSDiagnostic {
+      kind: diagnostic.kind.as_str().to_string(),
+      severity: diagnostic.severity.as_str().to_string(),
+      message: diagnostic.message.clone(),
+      file: diagnostic.file.clone(),
+      line: diagnostic.line,
+      column: diagnostic.column,
+      code_frame: diagnostic.code_frame.clone(),
+    }
+  }
+}
+
+pub fn to_js_warnings(warnings: &[Diagnostic]) -> Vec<JSDiagnostic> {
+  warnings.iter().map(JSDiagnostic::from).collect()
+}
+
+/*
+ * 生成带 diagnostic 属性的 JS Error
+ * try { styleFactory(css) } catch (e) { e.diagnostic.line }
+ */
+pub fn to_napi_error(env: Env, diagnostic: &Diagnostic) -> napi::Error {
+  let status = match diagnostic.kind {
+    DiagnosticKind::InvalidOption => napi::Status::InvalidArg,
+    _ => napi::Status::GenericFailure,
+  };
+  let reason = napi::Error::new(status, diagnostic.to_string());
+  let create_error = || -> napi::Result<napi::Error> {
+    let mut error = JsError::from(napi::Error::new(status, diagnostic.to_string()))
+      .into_unknown(env)
+      .coerce_to_object()?;
+    error.set_named_property("diagnostic", JSDiagnostic::from(diagnostic))?;
+    Ok(napi::Error::from(error.into_unknown()))
+  };
+  create_error().unwrap_or(reason)
+}
@@ -0,0 +1,318 @@
+use crate::assets::{Asset, RewriteUrl};
+use crate::compile_css::{ReadHook, ResolveHook, SourceHooks};
+use crate::js_asset::create_asset_object;
+use napi::threadsafe_function::{ThreadSafeCallContext, ThreadsafeFunctionCallMode};
+use napi::{
+  sys, CallContext, Env, JsFunction, JsUndefined, JsUnknown, NapiRaw, NapiValue, Ref, ValueType,
+};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/*
+ * JS 实现的 read / resolve / rewriteUrl, 只能在 JS 主线程中调用
+ * 打包在其他线程中进行, 每次调用发送到主线程执行并等待返回值
+ * 同步版本由主线程循环处理调用, 异步版本通过 threadsafe function 调用
+ */
+#[derive(Clone, Copy)]
+enum HookKind {
+  Read,
+  Resolve,
+  RewriteUrl,
+}
+
+impl HookKind {
+  fn name(&self) -> &'static str {
+    match self {
+      HookKind::Read => "read",
+      HookKind::Resolve => "resolve",
+      HookKind::RewriteUrl => "rewriteUrl",
+    }
+  }
+}
+
+enum HookArgs {
+  Strings(Vec<String>),
+  Asset(Asset),
+}
+
+type HookResult = Result<Option<String>, String>;
+
+struct HookCall {
+  kind: HookKind,
+  args: HookArgs,
+  reply: Sender<HookResult>,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct JsHookFunctions<'a> {
+  pub read: Option<&'a JsFunction>,
+  pub resolve: Option<&'a JsFunction>,
+  pub rewrite_url: Option<&'a JsFunction>,
+}
+
+impl JsHookFunctions<'_> {
+  pub fn is_empty(&self) -> bool {
+    self.read.is_none() && self.resolve.is_none() && self.rewrite_url.is_none()
+  }
+
+  fn get(&self, kind: HookKind) -> Option<&JsFunction> {
+    match kind {
+      HookKind::Read => self.read,
+      HookKind::Resolve => self.resolve,
+      HookKind::RewriteUrl => self.rewrite_url,
+    }
+  }
+}
+
+pub struct JsHooks {
+  pub source: SourceHooks,
+  pub rewrite_url: Option<RewriteUrl>,
+}
+
+fn js_hooks(
+  functions: JsHookFunctions,
+  send: impl Fn(HookCall) + Send + Sync + 'static,
+) -> JsHooks {
+  let request = Arc::new(move |kind: HookKind, args: HookArgs| {
+    let (reply, receiver) = mpsc::channel();
+    send(HookCall { kind, args, reply });
+    receiver
+      .recv()
+      .unwrap_or_else(|_| Err("JS hook was not called".to_string()))
+  });
+
+  JsHooks {
+    source: SourceHooks {
+      read: functions.read.map(|_| {
+        let request = request.clone();
+        Arc::new(move |path: &Path| {
+          request(
+            HookKind::Read,
+            HookArgs::Strings(vec![path_to_string(path)]),
+          )
+        }) as ReadHook
+      }),
+      resolve: functions.resolve.map(|_| {
+        let request = request.clone();
+        Arc::new(move |specifier: &str, originating_file: &Path| {
+          let args = vec![specifier.to_string(), path_to_string(originating_file)];
+          request(HookKind::Resolve, HookArgs::Strings(args)).map(|path| path.map(PathBuf::from))
+        }) as ResolveHook
+      }),
+    },
+    rewrite_url: functions.rewrite_url.map(|_| {
+      let request = request.clone();
+      Arc::new(move |asset: &Asset| request(HookKind::RewriteUrl, HookArgs::Asset(asset.clone())))
+        as RewriteUrl
+    }),
+  }
+}
+
+fn path_to_string(path: &Path) -> String {
+  path.to_string_lossy().to_string()
+}
+
+/*
+ * 取出并清除 JS 中未处理的异常, 否则之后的 napi 调用 (下一次 hook, 生成错误对象) 都会失败
+ * @return 异常的 message, 不是 Error 时转为字符串
+ */
+fn take_exception(env: &Env) -> Option<String> {
+  let mut pending = false;
+  let status = unsafe { sys::napi_is_exception_pending(env.raw(), &mut pending) };
+  if status != sys::Status::napi_ok || !pending {
+    return None;
+  }
+  let mut exception = ptr::null_mut();
+  let status = unsafe { sys::napi_get_and_clear_last_exception(env.raw(), &mut exception) };
+  if status != sys::Status::napi_ok {
+    return None;
+  }
+  let exception = unsafe { JsUnknown::from_raw_unchecked(env.raw(), exception) };
+  let message = || -> napi::Result<String> {
+    let value = match exception.get_type()? {
+      ValueType::Object => exception
+        .coerce_to_object()?
+        .get_named_property::<JsUnknown>("message")?,
+      _ => exception,
+    };
+    value.coerce_to_string()?.into_utf8()?.into_owned()
+  };
+  Some(message().unwrap_or_default())
+}
+
+/*
+ * 直接调用 napi_call_function, 自己取出抛出的异常
+ * JsFunction::call 只保留异常转成的字符串 "Error: <message>"
+ * @return 抛出异常时返回 Err(message)
+ */
+fn call_function(
+  env: &Env,
+  hook: &JsFunction,
+  args: &[JsUnknown],
+) -> napi::Result<Result<JsUnknown, String>> {
+  let this = env.get_undefined()?;
+  let args = args
+    .iter()
+    .map(|arg| unsafe { arg.raw() })
+    .collect::<Vec<_>>();
+  let mut value = ptr::null_mut();
+  let status = unsafe {
+    sys::napi_call_function(
+      env.raw(),
+      this.raw(),
+      hook.raw(),
+      args.len(),
+      args.as_ptr(),
+      &mut value,
+    )
+  };
+  match status {
+    sys::Status::napi_ok => Ok(Ok(unsafe { JsUnknown::from_raw(env.raw(), value)? })),
+    sys::Status::napi_pending_exception => Ok(Err(take_exception(env).unwrap_or_default())),
+    status => Err(napi::Error::new(
+      napi::Status::from(status),
+      "Call JS hook failed".to_string(),
+    )),
+  }
+}
+
+// 返回 null / undefined 时使用默认的实现, 抛出的异常转为错误信息
+fn call_js_hook(env: &Env, kind: HookKind, hook: &JsFunction, args: &HookArgs) -> HookResult {
+  let call = || -> napi::Result<HookResult> {
+    let args = match args {
+      HookArgs::Strings(args) => args
+        .iter()
+        .map(|arg| env.create_string(arg).map(|arg| arg.into_unknown()))
+        .collect::<napi::Result<Vec<_>>>()?,
+      HookArgs::Asset(asset) => vec![create_asset_object(env, asset)?.into_unknown()],
+    };
+    let value = match call_function(env, hook, &args)? {
+      Ok(value) => value,
+      Err(message) => return Ok(Err(format!("JS `{}` hook threw: {}", kind.name(), message))),
+    };
+    Ok(match value.get_type()? {
+      ValueType::Null | ValueType::Undefined => Ok(None),
+      ValueType::String => Ok(Some(value.coerce_to_string()?.into_utf8()?.into_owned()?)),
+      _ => Err(format!(
+        "JS `{}` hook must return a string, null or undefined",
+        kind.name()
+      )),
+    })
+  };
+  call().unwrap_or_else(|e| {
+    // 读取返回值等调用也可能抛出异常
+    let message = take_exception(env).unwrap_or(e.reason);
+    Err(format!("JS `{}` hook failed: {}", kind.name(), message))
+  })
+}
+
+/*
+ * 同步版本: 打包在子线程中进行, 当前线程处理 hooks 的调用直到打包结束
+ */
+pub fn with_js_hooks<T: Send>(
+  env: Env,
+  functions: JsHookFunctions,
+  run: impl FnOnce(JsHooks) -> T + Send,
+) -> T {
+  let (sender, receiver) = mpsc::channel::<HookCall>();
+  let hooks = js_hooks(functions, move |call| {
+    let _ = sender.send(call);
+  });
+
+  thread::scope(|scope| {
+    let handle = scope.spawn(move || run(hooks));
+    // hooks 全部释放后 receiver 结束
+    for call in receiver {
+      let result = match functions.get(call.kind) {
+        Some(hook) => call_js_hook(&env, call.kind, hook, &call.args),
+        None => Ok(None),
+      };
+      let _ = call.reply.send(result);
+    }
+    handle
+      .join()
+      .unwrap_or_else(|e| std::panic::resume_unwind(e))
+  })
+}
+
+// threadsafe function 需要一个 JS 函数, hooks 在回调中调用
+#[js_function]
+fn noop(ctx: CallContext) -> napi::Result<JsUndefined> {
+  ctx.env.get_undefined()
+}
+
+/*
+ * 异步版本: 保存 JS 函数的引用, 由 threadsafe function 在主线程中调用
+ * 任务结束后需要调用 release 释放引用
+ */
+struct HookRefs {
+  read: Option<Ref<()>>,
+  resolve: Option<Ref<()>>,
+  rewrite_url: Option<Ref<()>>,
+}
+
+#[derive(Clone)]
+pub struct JsHookRefs {
+  refs: Arc<Mutex<HookRefs>>,
+}
+
+impl JsHookRefs {
+  pub fn new(env: Env, functions: JsHookFunctions) -> napi::Result<(Self, JsHooks)> {
+    let reference =
+      |hook: Option<&JsFunction>| hook.map(|hook| env.create_reference(hook)).transpose();
+    let refs = JsHookRefs {
+      refs: Arc::new(Mutex::new(HookRefs {
+        read: reference(functions.read)?,
+        resolve: reference(functions.resolve)?,
+        rewrite_url: reference(functions.rewrite_url)?,
+      })),
+    };
+    let noop = env.create_function("noop", noop)?;
+    let tsfn = {
+      let refs = refs.clone();
+      env.create_threadsafe_function(&noop, 0, move |ctx: ThreadSafeCallContext<HookCall>| {
+        let result = refs.call(&ctx.env, ctx.value.kind, &ctx.value.args);
+        let _ = ctx.value.reply.send(result);
+        Ok(Vec::<JsUnknown>::new())
+      })?
+    };
+    let hooks = js_hooks(functions, move |call| {
+      tsfn.call(Ok(call), ThreadsafeFunctionCallMode::Blocking);
+    });
+    Ok((refs, hooks))
+  }
+
+  fn call(&self, env: &Env, kind: HookKind, args: &HookArgs) -> HookResult {
+    let refs = self.refs.lock().unwrap();
+    let hook = match kind {
+      HookKind::Read => &refs.read,
+      HookKind::Resolve => &refs.resolve,
+      HookKind::RewriteUrl => &refs.rewrite_url,
+    };
+    match hook {
+      Some(hook) => env
+        .get_reference_value::<JsFunction>(hook)
+        .map_err(|e| e.reason)
+        .and_then(|hook| call_js_hook(env, kind, &hook, args)),
+      None => Ok(None),
+    }
+  }
+
+  pub fn release(&self, env: Env) -> napi::Result<()> {
+    let mut refs = self.refs.lock().unwrap();
+    for mut hook in [
+      refs.read.take(),
+      refs.resolve.take(),
+      refs.rewrite_url.take(),
+    ]
+    .into_iter()
+    .flatten()
+    {
+      hook.unref(env)?;
+    }
+    Ok(())
+  }
+}
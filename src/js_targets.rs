@@ -0,0 +1,18 @@
+use crate::options::{get_targets, resolve_targets};
+use lightningcss::targets::Targets;
+use std::collections::HashMap;
+
+#[napi(object)]
+#[derive(Default)]
+pub struct JSTargets {
+  pub browserslist: Option<Vec<String>>,
+  pub browsers: Option<HashMap<String, String>>,
+}
+
+pub fn to_targets(targets: Option<&JSTargets>) -> Result<Targets, napi::Error> {
+  match targets {
+    Some(targets) => resolve_targets(targets.browserslist.as_deref(), targets.browsers.as_ref())
+      .map_err(|e| napi::Error::new(napi::Status::InvalidArg, e.to_string())),
+    None => Ok(get_targets()),
+  }
+}
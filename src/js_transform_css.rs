@@ -1,39 +1,105 @@
-use crate::transform_css::{transform_css, TransformCssOptions};
+use crate::diagnostic::Diagnostic;
+use crate::js_diagnostic::{to_js_warnings, to_napi_error, JSDiagnostic};
+use crate::js_targets::{to_targets, JSTargets};
+use crate::transform_css::{transform_css, TransformCssOptions, TransformCssResult};
+use lightningcss::targets::Targets;
+use napi::bindgen_prelude::AsyncTask;
+use napi::{Env, Task};
 use napi_derive::napi;
 
 #[napi(object)]
 pub struct JSTransformCSSResult {
   pub code: String,
+  pub warnings: Vec<JSDiagnostic>,
 }
 
 #[napi(object)]
+#[derive(Default)]
 pub struct JSTransformCssOptions {
   pub input: String,
   pub minify: Option<bool>,
-}
-
-impl Default for JSTransformCssOptions {
-  fn default() -> Self {
-    JSTransformCssOptions {
-      input: String::new(),
-      minify: None,
-    }
-  }
+  pub targets: Option<JSTargets>,
+  pub error_recovery: Option<bool>,
 }
 
 #[napi(js_name = "transformCSS")]
 pub fn js_transform_css(
+  env: Env,
   option: Option<JSTransformCssOptions>,
 ) -> Result<JSTransformCSSResult, napi::Error> {
   let option = option.unwrap_or_default();
   let minify = option.minify.unwrap_or(false);
   let input = option.input;
+  let targets = to_targets(option.targets.as_ref())?;
 
   let result = transform_css(TransformCssOptions {
     input: &input,
     minify,
+    targets,
+    error_recovery: option.error_recovery.unwrap_or(false),
   })
-  .map_err(|e| napi::Error::new(napi::Status::GenericFailure, e.to_string()))?;
+  .map_err(|e| to_napi_error(env, &e))?;
+
+  Ok(JSTransformCSSResult::from(result))
+}
+
+impl From<TransformCssResult> for JSTransformCSSResult {
+  fn from(result: TransformCssResult) -> Self {
+    JSTransformCSSResult {
+      code: result.code,
+      warnings: to_js_warnings(&result.warnings),
+    }
+  }
+}
+
+pub struct TransformCssTask {
+  input: String,
+  minify: bool,
+  targets: Targets,
+  error_recovery: bool,
+  diagnostic: Option<Diagnostic>,
+}
 
-  Ok(JSTransformCSSResult { code: result })
+impl Task for TransformCssTask {
+  type Output = TransformCssResult;
+  type JsValue = JSTransformCSSResult;
+
+  fn compute(&mut self) -> napi::Result<Self::Output> {
+    transform_css(TransformCssOptions {
+      input: &self.input,
+      minify: self.minify,
+      targets: self.targets,
+      error_recovery: self.error_recovery,
+    })
+    .map_err(|e| {
+      let error = napi::Error::from_reason(e.to_string());
+      self.diagnostic = Some(e);
+      error
+    })
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+    Ok(JSTransformCSSResult::from(output))
+  }
+
+  fn reject(&mut self, env: Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+    match self.diagnostic.take() {
+      Some(diagnostic) => Err(to_napi_error(env, &diagnostic)),
+      None => Err(err),
+    }
+  }
+}
+
+#[napi(js_name = "transformCSSAsync")]
+pub fn js_transform_css_async(
+  option: Option<JSTransformCssOptions>,
+) -> Result<AsyncTask<TransformCssTask>, napi::Error> {
+  let option = option.unwrap_or_default();
+  Ok(AsyncTask::new(TransformCssTask {
+    targets: to_targets(option.targets.as_ref())?,
+    input: option.input,
+    minify: option.minify.unwrap_or(false),
+    error_recovery: option.error_recovery.unwrap_or(false),
+    diagnostic: None,
+  }))
 }
@@ -3,16 +3,33 @@
 #[macro_use]
 extern crate napi_derive;
 
+mod assets;
 mod compile_css;
+mod compile_style_factory;
 mod convert_css;
 mod css_to_code;
+mod diagnostic;
+mod js_asset;
 mod js_compile_css;
+mod js_diagnostic;
+mod js_source_hooks;
+mod js_targets;
 mod js_transform_css;
+mod memory_provider;
 mod node_path;
 mod options;
+mod resolver;
+mod segment;
+mod source_map;
+mod static_css;
 mod style_factory;
+mod style_factory_batch;
 mod transform_css;
 
-pub use js_compile_css::js_compile_css;
-pub use js_transform_css::js_transform_css;
-pub use style_factory::style_factory;
+pub use compile_style_factory::{js_compile_style_factory, js_compile_style_factory_async};
+pub use js_compile_css::{js_compile_css, js_compile_css_async};
+pub use js_transform_css::{js_transform_css, js_transform_css_async};
+pub use style_factory::{
+  style_factory, style_factory_async, style_factory_result, style_factory_result_async,
+};
+pub use style_factory_batch::style_factory_batch;
@@ -0,0 +1,114 @@
+use crate::node_path::normalize;
+use crate::resolver::{FileSystem, Resolver};
+use lightningcss::bundler::{FileProvider, SourceProvider};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/*
+ * 内存中的文件, 如编辑器中未保存的内容或生成的样式
+ * fallback 为 true 时内存中不存在的文件从磁盘读取
+ */
+#[derive(Debug, Default)]
+pub struct MemoryFiles {
+  files: HashMap<PathBuf, String>,
+  fallback: bool,
+}
+
+impl MemoryFiles {
+  pub fn new(files: impl IntoIterator<Item = (PathBuf, String)>, fallback: bool) -> Self {
+    MemoryFiles {
+      files: files
+        .into_iter()
+        .map(|(path, source)| (normalize(path), source))
+        .collect(),
+      fallback,
+    }
+  }
+
+  pub fn get(&self, path: &Path) -> Option<&str> {
+    self.files.get(&normalize(path)).map(String::as_str)
+  }
+}
+
+impl FileSystem for MemoryFiles {
+  fn is_file(&self, path: &Path) -> bool {
+    self.get(path).is_some() || (self.fallback && path.is_file())
+  }
+
+  fn is_dir(&self, path: &Path) -> bool {
+    let dir = normalize(path);
+    self
+      .files
+      .keys()
+      .any(|file| file != &dir && file.starts_with(&dir))
+      || (self.fallback && path.is_dir())
+  }
+
+  fn read_to_string(&self, path: &Path) -> io::Result<String> {
+    match self.get(path) {
+      Some(source) => Ok(source.to_string()),
+      None if self.fallback => fs::read_to_string(path),
+      None => Err(not_found(path)),
+    }
+  }
+
+  fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+    match self.get(path) {
+      Some(source) => Ok(source.as_bytes().to_vec()),
+      None if self.fallback => fs::read(path),
+      None => Err(not_found(path)),
+    }
+  }
+
+  fn size(&self, path: &Path) -> io::Result<u64> {
+    match self.get(path) {
+      Some(source) => Ok(source.len() as u64),
+      None if self.fallback => Ok(fs::metadata(path)?.len()),
+      None => Err(not_found(path)),
+    }
+  }
+}
+
+pub struct MemoryProvider {
+  files: Arc<MemoryFiles>,
+  file_provider: FileProvider,
+  resolver: Resolver,
+}
+
+impl MemoryProvider {
+  pub fn new(files: Arc<MemoryFiles>, resolver: Resolver) -> Self {
+    MemoryProvider {
+      files,
+      file_provider: FileProvider::new(),
+      resolver,
+    }
+  }
+}
+
+impl SourceProvider for MemoryProvider {
+  type Error = io::Error;
+
+  fn read(&self, path: &Path) -> Result<&str, Self::Error> {
+    match self.files.get(path) {
+      Some(source) => Ok(source),
+      None if self.files.fallback => self.file_provider.read(path),
+      None => Err(not_found(path)),
+    }
+  }
+
+  fn resolve(&self, specifier: &str, originating_file: &Path) -> Result<PathBuf, Self::Error> {
+    self
+      .resolver
+      .resolve(specifier, originating_file, self.files.as_ref())
+  }
+}
+
+fn not_found(path: &Path) -> io::Error {
+  io::Error::new(
+    io::ErrorKind::NotFound,
+    format!("File not found in memory: {}", path.display()),
+  )
+}
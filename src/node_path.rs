@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 
 /*
  * Get the basename of a path
@@ -14,3 +14,37 @@ pub fn get_basename<P: AsRef<Path>>(path: P, with_extension: bool) -> Option<Str
     path.file_stem()?.to_str().map(String::from)
   }
 }
+
+/*
+ * 与 node 的 path.normalize 一致, 只按字面处理 . 和 .., 不访问文件系统
+ * @param path: a/./b/../c => a/c
+ */
+pub fn normalize<P: AsRef<Path>>(path: P) -> PathBuf {
+  let mut normalized = PathBuf::new();
+  for component in path.as_ref().components() {
+    match component {
+      Component::CurDir => {}
+      Component::ParentDir => match normalized.components().next_back() {
+        Some(Component::Normal(_)) => {
+          normalized.pop();
+        }
+        Some(Component::RootDir | Component::Prefix(_)) => {}
+        _ => normalized.push(".."),
+      },
+      component => normalized.push(component),
+    }
+  }
+  normalized
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_normalize() {
+    assert_eq!(normalize("/a/./b/../c.css"), PathBuf::from("/a/c.css"));
+    assert_eq!(normalize("a/../../b.css"), PathBuf::from("../b.css"));
+    assert_eq!(normalize("/../a.css"), PathBuf::from("/a.css"));
+  }
+}
@@ -1,8 +1,13 @@
 use lightningcss::printer::PrinterOptions;
 use lightningcss::stylesheet::{MinifyOptions, ParserOptions};
 use lightningcss::targets::{Browsers, Targets};
+use std::collections::HashMap;
+use std::error::Error;
 
-pub fn get_targets<'t>() -> Targets {
+/*
+ * 默认的目标浏览器: Safari 11 / Chrome 55
+ */
+pub fn get_targets() -> Targets {
   Targets::from(Browsers {
     safari: Some(11 << 16),
     chrome: Some(55 << 16),
@@ -10,10 +15,68 @@ pub fn get_targets<'t>() -> Targets {
   })
 }
 
-pub fn get_printer_options<'a>() -> PrinterOptions<'a> {
+/*
+ * 按 browserslist 查询或浏览器版本表生成目标浏览器, 都未设置时使用默认值
+ * @param query: browserslist 查询  ["safari >= 11", "chrome >= 55"]
+ * @param versions: 浏览器 => 版本  { "safari": "11", "ios_saf": "12.2" }
+ */
+pub fn resolve_targets(
+  query: Option<&[String]>,
+  versions: Option<&HashMap<String, String>>,
+) -> Result<Targets, Box<dyn Error>> {
+  match (query, versions) {
+    (Some(_), Some(_)) => {
+      Err("Invalid targets: `browserslist` and `browsers` cannot be used together".into())
+    }
+    (Some(query), None) => {
+      let browsers = Browsers::from_browserslist(query)
+        .map_err(|e| format!("Invalid browserslist query: {}", e))?
+        .ok_or_else(|| format!("Browserslist query matched no browsers: {:?}", query))?;
+      Ok(Targets::from(browsers))
+    }
+    (None, Some(versions)) => {
+      let mut browsers = Browsers::default();
+      for (name, version) in versions {
+        let version = Some(parse_version(name, version)?);
+        match name.as_str() {
+          "android" => browsers.android = version,
+          "chrome" => browsers.chrome = version,
+          "edge" => browsers.edge = version,
+          "firefox" => browsers.firefox = version,
+          "ie" => browsers.ie = version,
+          "ios_saf" | "ios" => browsers.ios_saf = version,
+          "opera" => browsers.opera = version,
+          "safari" => browsers.safari = version,
+          "samsung" => browsers.samsung = version,
+          _ => return Err(format!("Invalid targets: unknown browser `{}`", name).into()),
+        }
+      }
+      Ok(Targets::from(browsers))
+    }
+    (None, None) => Ok(get_targets()),
+  }
+}
+
+// "13.4.1" => 13 << 16 | 4 << 8 | 1
+fn parse_version(name: &str, version: &str) -> Result<u32, Box<dyn Error>> {
+  let invalid = || format!("Invalid targets: bad version `{}` for `{}`", version, name);
+  let parts = version.trim().split('.').collect::<Vec<_>>();
+  if parts.is_empty() || parts.len() > 3 {
+    return Err(invalid().into());
+  }
+
+  let mut result = 0;
+  for (index, part) in parts.iter().enumerate() {
+    let value = part.parse::<u8>().map_err(|_| invalid())?;
+    result |= (value as u32) << (16 - index * 8);
+  }
+  Ok(result)
+}
+
+pub fn get_printer_options<'a>(targets: Targets) -> PrinterOptions<'a> {
   PrinterOptions {
     minify: true,
-    targets: get_targets(),
+    targets,
     ..PrinterOptions::default()
   }
 }
@@ -22,9 +85,47 @@ pub fn get_parser_options<'p, 'q>() -> ParserOptions<'p, 'q> {
   ParserOptions::default()
 }
 
-pub fn get_minify_options() -> MinifyOptions {
+pub fn get_minify_options(targets: Targets) -> MinifyOptions {
   MinifyOptions {
-    targets: get_targets(),
+    targets,
     ..MinifyOptions::default()
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_resolve_targets_versions() {
+    let versions = HashMap::from([
+      ("safari".to_string(), "13.4.1".to_string()),
+      ("ios".to_string(), "12".to_string()),
+    ]);
+    let targets = resolve_targets(None, Some(&versions)).unwrap();
+    let browsers = targets.browsers.unwrap();
+    assert_eq!(browsers.safari, Some(13 << 16 | 4 << 8 | 1));
+    assert_eq!(browsers.ios_saf, Some(12 << 16));
+    assert_eq!(browsers.chrome, None);
+  }
+
+  #[test]
+  fn test_resolve_targets_errors() {
+    let bad_version = HashMap::from([("chrome".to_string(), "latest".to_string())]);
+    let err = resolve_targets(None, Some(&bad_version)).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "Invalid targets: bad version `latest` for `chrome`"
+    );
+
+    let bad_browser = HashMap::from([("netscape".to_string(), "4".to_string())]);
+    let err = resolve_targets(None, Some(&bad_browser)).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "Invalid targets: unknown browser `netscape`"
+    );
+
+    let query = vec!["chrome >= 55".to_string()];
+    assert!(resolve_targets(Some(&query), Some(&bad_browser)).is_err());
+  }
+}
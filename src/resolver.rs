@@ -0,0 +1,273 @@
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/*
+ * @import 的路径解析
+ * 1. ~ 开头的路径只在模块目录中查找  ~@ui/base.css
+ * 2. 按前缀匹配别名  @/styles/vars.css => <src>/styles/vars.css
+ * 3. 其他路径先按相对路径查找, 找不到时再到模块目录中查找
+ * 都找不到时与 FileProvider 一致返回相对路径, 由读取文件时报错
+ */
+// 解析时查找文件的方式, 磁盘或内存中的文件
+pub trait FileSystem {
+  fn is_file(&self, path: &Path) -> bool;
+  fn is_dir(&self, path: &Path) -> bool;
+  fn read_to_string(&self, path: &Path) -> io::Result<String>;
+  fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+  // 文件的字节数, 用于在读取前判断大小
+  fn size(&self, path: &Path) -> io::Result<u64>;
+}
+
+pub struct OsFileSystem;
+
+impl FileSystem for OsFileSystem {
+  fn is_file(&self, path: &Path) -> bool {
+    path.is_file()
+  }
+
+  fn is_dir(&self, path: &Path) -> bool {
+    path.is_dir()
+  }
+
+  fn read_to_string(&self, path: &Path) -> io::Result<String> {
+    fs::read_to_string(path)
+  }
+
+  fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+    fs::read(path)
+  }
+
+  fn size(&self, path: &Path) -> io::Result<u64> {
+    Ok(fs::metadata(path)?.len())
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct Resolver {
+  // 别名 => 目录或文件, 最长的别名优先匹配
+  pub alias: Vec<(String, PathBuf)>,
+  // 目录名时从引入文件所在目录逐级向上查找, 绝对路径时直接查找
+  pub module_directories: Vec<String>,
+  // 省略扩展名时依次尝试
+  pub extensions: Vec<String>,
+  // 引入整个包时依次读取 package.json 中的字段
+  pub main_fields: Vec<String>,
+}
+
+impl Default for Resolver {
+  fn default() -> Self {
+    Resolver {
+      alias: Vec::new(),
+      module_directories: vec!["node_modules".to_string()],
+      extensions: vec![".css".to_string()],
+      main_fields: vec!["style".to_string()],
+    }
+  }
+}
+
+impl Resolver {
+  pub fn resolve(
+    &self,
+    specifier: &str,
+    originating_file: &Path,
+    fs: &dyn FileSystem,
+  ) -> io::Result<PathBuf> {
+    let base_dir = originating_file.parent().unwrap_or(Path::new(""));
+
+    if let Some(request) = specifier.strip_prefix('~') {
+      return self.resolve_module(request, base_dir, fs).ok_or_else(|| {
+        io::Error::new(
+          io::ErrorKind::NotFound,
+          format!(
+            "Cannot resolve `{}` from `{}`",
+            specifier,
+            originating_file.display()
+          ),
+        )
+      });
+    }
+
+    if let Some(path) = self.resolve_alias(specifier) {
+      return Ok(self.resolve_path(&path, fs).unwrap_or(path));
+    }
+
+    let relative = base_dir.join(specifier);
+    if let Some(path) = self.resolve_path(&relative, fs) {
+      return Ok(path);
+    }
+    if !is_relative(specifier) {
+      if let Some(path) = self.resolve_module(specifier, base_dir, fs) {
+        return Ok(path);
+      }
+    }
+    Ok(relative)
+  }
+
+  fn resolve_alias(&self, specifier: &str) -> Option<PathBuf> {
+    self
+      .alias
+      .iter()
+      .filter(|(name, _)| {
+        specifier == name
+          || specifier
+            .strip_prefix(name.as_str())
+            .is_some_and(|rest| rest.starts_with('/'))
+      })
+      .max_by_key(|(name, _)| name.len())
+      .map(|(name, target)| {
+        let rest = specifier[name.len()..].trim_start_matches('/');
+        match rest {
+          "" => target.clone(),
+          rest => target.join(rest),
+        }
+      })
+  }
+
+  fn resolve_module(&self, request: &str, base_dir: &Path, fs: &dyn FileSystem) -> Option<PathBuf> {
+    self.module_directories.iter().find_map(|directory| {
+      let directory = Path::new(directory);
+      if directory.is_absolute() {
+        return self.resolve_path(&directory.join(request), fs);
+      }
+      base_dir
+        .ancestors()
+        .find_map(|ancestor| self.resolve_path(&ancestor.join(directory).join(request), fs))
+    })
+  }
+
+  // 文件 => 补全扩展名 => 目录中的 package.json / index
+  fn resolve_path(&self, path: &Path, fs: &dyn FileSystem) -> Option<PathBuf> {
+    if fs.is_file(path) {
+      return Some(path.to_path_buf());
+    }
+    if let Some(path) = self.with_extensions(path, fs) {
+      return Some(path);
+    }
+    if fs.is_dir(path) {
+      return self
+        .resolve_package(path, fs)
+        .or_else(|| self.with_extensions(&path.join("index"), fs));
+    }
+    None
+  }
+
+  fn with_extensions(&self, path: &Path, fs: &dyn FileSystem) -> Option<PathBuf> {
+    self.extensions.iter().find_map(|extension| {
+      let mut file = path.as_os_str().to_owned();
+      file.push(extension);
+      let file = PathBuf::from(file);
+      fs.is_file(&file).then_some(file)
+    })
+  }
+
+  fn resolve_package(&self, dir: &Path, fs: &dyn FileSystem) -> Option<PathBuf> {
+    let package = fs.read_to_string(&dir.join("package.json")).ok()?;
+    let package = serde_json::from_str::<Value>(&package).ok()?;
+    self.main_fields.iter().find_map(|field| {
+      let entry = package.get(field)?.as_str()?;
+      let path = dir.join(entry);
+      match fs.is_file(&path) {
+        true => Some(path),
+        false => self.with_extensions(&path, fs),
+      }
+    })
+  }
+}
+
+fn is_relative(specifier: &str) -> bool {
+  specifier.starts_with("./") || specifier.starts_with("../") || Path::new(specifier).is_absolute()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::tempdir;
+
+  #[test]
+  fn test_resolve() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    let files = [
+      "packages/app/src/index.css",
+      "packages/app/src/styles/vars.css",
+      "packages/app/src/components/button/index.css",
+      "node_modules/@ui/base/package.json",
+      "node_modules/@ui/base/dist/base.css",
+      "node_modules/@ui/base/theme.css",
+      "node_modules/reset/index.css",
+      "shared/colors.css",
+    ];
+    for file in files {
+      let path = root.join(file);
+      fs::create_dir_all(path.parent().unwrap()).unwrap();
+      fs::write(&path, "").unwrap();
+    }
+    fs::write(
+      root.join("node_modules/@ui/base/package.json"),
+      r#"{ "main": "index.js", "style": "dist/base.css" }"#,
+    )
+    .unwrap();
+
+    let src = root.join("packages/app/src");
+    let resolver = Resolver {
+      alias: vec![
+        ("@".to_string(), src.clone()),
+        ("@components".to_string(), src.join("components")),
+      ],
+      module_directories: vec![
+        "node_modules".to_string(),
+        root.join("shared").to_string_lossy().to_string(),
+      ],
+      ..Resolver::default()
+    };
+    let from = src.join("index.css");
+    let resolve = |specifier: &str| {
+      resolver
+        .resolve(specifier, &from, &OsFileSystem)
+        .map(|path| path.strip_prefix(root).unwrap().to_path_buf())
+        .map_err(|e| e.to_string())
+    };
+
+    assert_eq!(
+      resolve("./styles/vars.css"),
+      Ok(PathBuf::from("packages/app/src/styles/vars.css"))
+    );
+    assert_eq!(
+      resolve("styles/vars"),
+      Ok(PathBuf::from("packages/app/src/styles/vars.css"))
+    );
+    assert_eq!(
+      resolve("@/styles/vars.css"),
+      Ok(PathBuf::from("packages/app/src/styles/vars.css"))
+    );
+    assert_eq!(
+      resolve("@components/button"),
+      Ok(PathBuf::from(
+        "packages/app/src/components/button/index.css"
+      ))
+    );
+    assert_eq!(
+      resolve("~@ui/base"),
+      Ok(PathBuf::from("node_modules/@ui/base/dist/base.css"))
+    );
+    assert_eq!(
+      resolve("~@ui/base/theme"),
+      Ok(PathBuf::from("node_modules/@ui/base/theme.css"))
+    );
+    assert_eq!(
+      resolve("reset"),
+      Ok(PathBuf::from("node_modules/reset/index.css"))
+    );
+    assert_eq!(
+      resolve("colors.css"),
+      Ok(PathBuf::from("shared/colors.css"))
+    );
+    assert_eq!(
+      resolve("./missing.css"),
+      Ok(PathBuf::from("packages/app/src/missing.css"))
+    );
+    assert!(resolve("~missing").is_err());
+  }
+}
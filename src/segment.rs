@@ -0,0 +1,304 @@
+use crate::css_to_code::md5_hash;
+use crate::source_map::{position_to_offset, TrackedText};
+use std::fmt;
+
+/*
+ * convert_css 输出的 css 片段, css_to_code 按片段生成代码, 不需要再匹配文本中的占位符
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+  Text(String),
+  // .a => "." + prefix + "a"
+  Prefix,
+  // :host => [is=host]
+  Host,
+  // 100rpx => rpx(100) + "px", func 是运行时转换函数的名称
+  Unit { func: String, value: String },
+  // @media (max-width: 600rpx) => rpx(600) + "px"
+  MediaUnit { func: String, value: String },
+  // @import url('./a.css') => I_xxx(options)
+  Import(String),
+  // div => tag("div")
+  Tag(String),
+}
+
+// 样式中出现的类名, scoped 是运行时实际使用的类名
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScopedClass {
+  pub name: String,
+  pub scoped: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CssSegments {
+  pub segments: Vec<Segment>,
+  // 每个片段在 lightningcss 输出中的起始偏移量
+  starts: Vec<usize>,
+  // lightningcss 的输出, 用于把 source map 中的行列换算成偏移量
+  printed: String,
+}
+
+impl CssSegments {
+  // lightningcss 输出中的 line / column => (片段下标, 片段内的字节偏移量)
+  pub fn locate(&self, line: u32, column: u32) -> Option<(usize, usize)> {
+    let offset = position_to_offset(&self.printed, line, column, false);
+    let index = self
+      .starts
+      .partition_point(|start| *start <= offset)
+      .checked_sub(1)?;
+    Some((index, offset - self.starts[index]))
+  }
+
+  fn push(&mut self, start: usize, segment: Segment) {
+    if let Segment::Text(text) = &segment {
+      if text.is_empty() {
+        return;
+      }
+    }
+    self.starts.push(start);
+    self.segments.push(segment);
+  }
+}
+
+// 按之前的占位符格式输出, 便于调试和快照测试
+impl fmt::Display for Segment {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Segment::Text(text) => f.write_str(text),
+      Segment::Prefix => f.write_str("__PREFIX__"),
+      Segment::Host => f.write_str("__HOST__"),
+      Segment::Unit { func, value } => {
+        write!(f, r#""__{}__({})""#, func.to_uppercase(), value)
+      }
+      Segment::MediaUnit { func, value } => {
+        write!(f, "env(--__{}__,{})", func.to_uppercase(), value)
+      }
+      Segment::Import(url) => write!(f, r#"@import-style ("{}");"#, url),
+      Segment::Tag(name) => write!(f, "__TAG__({})", name),
+    }
+  }
+}
+
+impl fmt::Display for CssSegments {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for segment in &self.segments {
+      write!(f, "{}", segment)?;
+    }
+    Ok(())
+  }
+}
+
+// 直接由片段构造, 位置按 Display 的输出计算
+impl From<Vec<Segment>> for CssSegments {
+  fn from(segments: Vec<Segment>) -> Self {
+    let mut result = CssSegments::default();
+    for segment in segments {
+      let start = result.printed.len();
+      result.printed.push_str(&segment.to_string());
+      result.push(start, segment);
+    }
+    result
+  }
+}
+
+/*
+ * 打印前写入 CSS 的占位符
+ * 占位符带有根据输入内容计算的 nonce, 用户输入的内容无法和占位符重复
+ */
+#[derive(Debug, Clone)]
+pub struct Placeholders {
+  stem: String,
+}
+
+impl Placeholders {
+  pub fn new(css: &str) -> Self {
+    Placeholders {
+      stem: format!("__SF{}", &md5_hash(css)[..8]),
+    }
+  }
+
+  // 类名前缀: .a => .<prefix>a
+  pub fn prefix(&self) -> String {
+    format!("{}P__", self.stem)
+  }
+
+  // :host => [is=<host>]
+  pub fn host(&self) -> String {
+    format!("{}H__", self.stem)
+  }
+
+  // 100rpx => "<unit>rpx(100)"
+  pub fn unit(&self, func: &str, value: &str) -> String {
+    format!("{}U__{}({})", self.stem, func, value)
+  }
+
+  // @media (max-width: 600rpx) => @media (max-width: env(--<unit>rpx,600))
+  pub fn media_unit(&self, func: &str, value: &str) -> String {
+    format!("env(--{}U__{},{})", self.stem, func, value)
+  }
+
+  // @import url('./a.css') => @<import> 0;
+  pub fn import(&self) -> String {
+    format!("{}I__", self.stem)
+  }
+
+  // div => <tag>div
+  pub fn tag(&self, name: &str) -> String {
+    format!("{}T__{}", self.stem, name)
+  }
+
+  // env(--<unit>rpx,600) => 600rpx, 还原打包前替换的 media query 单位
+  pub fn restore_media_units(&self, text: &str) -> TrackedText {
+    let marker = format!("env(--{}U__", self.stem);
+    let mut replacements = Vec::new();
+    let mut start = 0;
+    while let Some(position) = text[start..].find(&marker).map(|index| start + index) {
+      let after = &text[position + marker.len()..];
+      let Some((close, (unit, value))) = after
+        .find(')')
+        .and_then(|close| Some((close, after[..close].split_once(',')?)))
+      else {
+        break;
+      };
+      start = position + marker.len() + close + 1;
+      replacements.push((position..start, format!("{}{}", value.trim(), unit)));
+    }
+    let mut tracked = TrackedText::new(text);
+    tracked.replace_ranges(replacements);
+    tracked
+  }
+
+  /*
+   * 把 lightningcss 的输出按占位符切分成片段
+   * @param imports: @import 的地址, 按占位符中的下标查找
+   */
+  pub fn split(&self, printed: String, imports: &[String]) -> Result<CssSegments, String> {
+    let mut result = CssSegments::default();
+    let mut cursor = 0;
+
+    for (position, _) in printed.match_indices(&self.stem) {
+      if position < cursor {
+        continue;
+      }
+      let invalid = || format!("Invalid placeholder at offset {}", position);
+      let marker_end = position + self.stem.len() + 3;
+      let kind = printed
+        .get(position + self.stem.len()..marker_end)
+        .ok_or_else(invalid)?;
+      let before = &printed[..position];
+      let after = &printed[marker_end..];
+
+      let (start, segment, end) = match kind {
+        "P__" => (position, Segment::Prefix, marker_end),
+        "H__" => (position, Segment::Host, marker_end),
+        "U__" if before.ends_with('"') => {
+          let open = after.find('(').ok_or_else(invalid)?;
+          let close = after.find(")\"").ok_or_else(invalid)?;
+          let segment = Segment::Unit {
+            func: after[..open].to_string(),
+            value: after.get(open + 1..close).ok_or_else(invalid)?.to_string(),
+          };
+          (position - 1, segment, marker_end + close + 2)
+        }
+        "U__" if before.ends_with("env(--") => {
+          let comma = after.find(',').ok_or_else(invalid)?;
+          let close = after.find(')').ok_or_else(invalid)?;
+          let segment = Segment::MediaUnit {
+            func: after[..comma].to_string(),
+            value: after
+              .get(comma + 1..close)
+              .ok_or_else(invalid)?
+              .trim()
+              .to_string(),
+          };
+          (position - 6, segment, marker_end + close + 1)
+        }
+        "I__" if before.ends_with('@') => {
+          let close = after.find(';').ok_or_else(invalid)?;
+          let url = after[..close]
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| imports.get(index))
+            .ok_or_else(invalid)?;
+          (
+            position - 1,
+            Segment::Import(url.clone()),
+            marker_end + close + 1,
+          )
+        }
+        "T__" => {
+          let len = after
+            .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '-' || ch == '_'))
+            .unwrap_or(after.len());
+          if len == 0 {
+            return Err(invalid());
+          }
+          let name = after[..len].to_string();
+          (position, Segment::Tag(name), marker_end + len)
+        }
+        _ => return Err(invalid()),
+      };
+
+      result.push(cursor, Segment::Text(printed[cursor..start].to_string()));
+      result.push(start, segment);
+      cursor = end;
+    }
+    result.push(cursor, Segment::Text(printed[cursor..].to_string()));
+    result.printed = printed;
+
+    Ok(result)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_split() {
+    let placeholders = Placeholders::new(".a{}");
+    let printed = format!(
+      r#"@{} 0;.{}a{{width:"{}"}}[is={}]{{}}@media (max-width:{}){{}}{}+{}{{}}"#,
+      placeholders.import(),
+      placeholders.prefix(),
+      placeholders.unit("rpx", "20"),
+      placeholders.host(),
+      placeholders.media_unit("hd", "600"),
+      placeholders.tag("view"),
+      placeholders.tag("scroll-view"),
+    );
+    let segments = placeholders
+      .split(printed, &["./__PREFIX__).css".to_string()])
+      .unwrap();
+
+    assert_eq!(
+      segments.segments,
+      vec![
+        Segment::Import("./__PREFIX__).css".to_string()),
+        Segment::Text(".".to_string()),
+        Segment::Prefix,
+        Segment::Text("a{width:".to_string()),
+        Segment::Unit {
+          func: "rpx".to_string(),
+          value: "20".to_string(),
+        },
+        Segment::Text("}[is=".to_string()),
+        Segment::Host,
+        Segment::Text("]{}@media (max-width:".to_string()),
+        Segment::MediaUnit {
+          func: "hd".to_string(),
+          value: "600".to_string(),
+        },
+        Segment::Text("){}".to_string()),
+        Segment::Tag("view".to_string()),
+        Segment::Text("+".to_string()),
+        Segment::Tag("scroll-view".to_string()),
+        Segment::Text("{}".to_string()),
+      ]
+    );
+    assert_eq!(segments.locate(0, 0), Some((0, 0)));
+    assert_eq!(segments.locate(0, 19), Some((1, 0)));
+    assert_eq!(segments.locate(0, 20), Some((2, 0)));
+  }
+}
@@ -0,0 +1,136 @@
+use std::ops::Range;
+
+// 一次替换: 把 [start, end) 替换成长度为 len 的新文本
+#[derive(Debug, Clone, Copy)]
+struct Edit {
+  start: usize,
+  end: usize,
+  len: usize,
+}
+
+/*
+ * 记录每一轮替换的文本, 用于把原始文本的偏移量映射到替换后的文本
+ */
+#[derive(Debug, Clone)]
+pub struct TrackedText {
+  pub text: String,
+  passes: Vec<Vec<Edit>>,
+}
+
+impl TrackedText {
+  pub fn new<S: Into<String>>(text: S) -> Self {
+    TrackedText {
+      text: text.into(),
+      passes: Vec::new(),
+    }
+  }
+
+  // replacements 必须按顺序排列且互不重叠
+  pub fn replace_ranges(&mut self, replacements: Vec<(Range<usize>, String)>) {
+    if replacements.is_empty() {
+      return;
+    }
+
+    let mut result = String::with_capacity(self.text.len());
+    let mut edits = Vec::with_capacity(replacements.len());
+    let mut last = 0;
+    for (range, replacement) in replacements {
+      result.push_str(&self.text[last..range.start]);
+      result.push_str(&replacement);
+      edits.push(Edit {
+        start: range.start,
+        end: range.end,
+        len: replacement.len(),
+      });
+      last = range.end;
+    }
+    result.push_str(&self.text[last..]);
+
+    self.text = result;
+    self.passes.push(edits);
+  }
+
+  // 当前文本偏移量 => 原始文本偏移量
+  pub fn unmap_offset(&self, offset: usize) -> usize {
+    self.passes.iter().rev().fold(offset, |offset, edits| {
+      let mut delta = 0isize;
+      for edit in edits {
+        let start = (edit.start as isize + delta) as usize;
+        let end = start + edit.len;
+        if end <= offset && start < offset {
+          delta += edit.len as isize - (edit.end - edit.start) as isize;
+        } else if start <= offset && offset < end {
+          return edit.start;
+        } else {
+          break;
+        }
+      }
+      (offset as isize - delta) as usize
+    })
+  }
+}
+
+/*
+ * line / column => 字节偏移量
+ * @param utf16: column 是否以 UTF-16 为单位 (cssparser), 否则以字节为单位 (lightningcss printer)
+ */
+pub fn position_to_offset(text: &str, line: u32, column: u32, utf16: bool) -> usize {
+  let line_start = match line {
+    0 => 0,
+    _ => text
+      .match_indices('\n')
+      .nth(line as usize - 1)
+      .map_or(text.len(), |(index, _)| index + 1),
+  };
+  let line_text = &text[line_start..];
+
+  if !utf16 {
+    return (line_start + column as usize).min(text.len());
+  }
+
+  let mut units = 0;
+  for (index, ch) in line_text.char_indices() {
+    if units >= column as usize || ch == '\n' {
+      return line_start + index;
+    }
+    units += ch.len_utf16();
+  }
+  text.len()
+}
+
+// 字节偏移量 => (line, UTF-16 column)
+pub fn offset_to_position(text: &str, offset: usize) -> (u32, u32) {
+  let before = &text[..offset.min(text.len())];
+  let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+  let line = before.matches('\n').count();
+  let column = before[line_start..].encode_utf16().count();
+  (line as u32, column as u32)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_tracked_text_offsets() {
+    let mut tracked = TrackedText::new(r#"a"b.__P__c"#);
+    tracked.replace_ranges(vec![(1..2, r#"\""#.to_string())]);
+    tracked.replace_ranges(vec![(5..10, "<prefix>".to_string())]);
+    assert_eq!(tracked.text, r#"a\"b.<prefix>c"#);
+
+    // a => a, b => b, " => \", c => c
+    assert_eq!(tracked.unmap_offset(3), 2);
+    assert_eq!(tracked.unmap_offset(8), 4);
+    assert_eq!(tracked.unmap_offset(13), 9);
+  }
+
+  #[test]
+  fn test_positions() {
+    let text = "ab\n中c\nd";
+    assert_eq!(position_to_offset(text, 1, 1, true), 6);
+    assert_eq!(position_to_offset(text, 1, 3, false), 6);
+    assert_eq!(position_to_offset(text, 2, 0, true), 8);
+    assert_eq!(offset_to_position(text, 6), (1, 1));
+    assert_eq!(offset_to_position(text, 8), (2, 0));
+  }
+}
@@ -0,0 +1,136 @@
+use crate::segment::{CssSegments, Segment};
+use std::collections::HashMap;
+
+/*
+ * 不生成 JS, 直接用具体的值替换占位符, 输出可以写入 .css 文件的样式
+ * 与 styleFactory(options) 在运行时拼接的结果一致
+ */
+#[derive(Debug, Clone)]
+pub struct StaticCssOptions {
+  pub prefix: String,
+  pub host: String,
+  // 运行时单位转换函数对应的倍数  { rpx: 0.5 } => 100rpx => 50px
+  pub unit_factors: HashMap<String, f64>,
+}
+
+impl Default for StaticCssOptions {
+  fn default() -> Self {
+    StaticCssOptions {
+      prefix: String::new(),
+      host: "host-placeholder".to_string(),
+      unit_factors: HashMap::new(),
+    }
+  }
+}
+
+/*
+ * @param host_css: 与运行时未传入 hostStyle 时一致, 拼接在样式之前, @import 之后
+ * @return 缺少单位转换倍数时返回错误
+ */
+pub fn css_to_static(
+  css: &CssSegments,
+  host_css: Option<&CssSegments>,
+  options: &StaticCssOptions,
+) -> Result<String, String> {
+  let mut imports = String::new();
+  let mut output = String::new();
+  for segments in host_css.into_iter().chain([css]) {
+    render_segments(segments, options, &mut imports, &mut output)?;
+  }
+  Ok(imports + &output)
+}
+
+fn render_segments(
+  css: &CssSegments,
+  options: &StaticCssOptions,
+  imports: &mut String,
+  output: &mut String,
+) -> Result<(), String> {
+  for segment in &css.segments {
+    match segment {
+      Segment::Text(text) => output.push_str(text),
+      Segment::Prefix => output.push_str(&options.prefix),
+      Segment::Host => output.push_str(&quote_host(&options.host)),
+      Segment::Unit { func, value } | Segment::MediaUnit { func, value } => {
+        let factor = options
+          .unit_factors
+          .get(func)
+          .ok_or_else(|| format!("Missing unit factor for `{}`", func))?;
+        let value = value
+          .parse::<f64>()
+          .map_err(|_| format!("Invalid {} value `{}`", func, value))?;
+        output.push_str(&format!("{}px", value * factor));
+      }
+      // 无法在静态样式中内联, 保留为 css 的 @import
+      Segment::Import(url) => imports.push_str(&format!(
+        r#"@import "{}";"#,
+        url.replace('\\', r"\\").replace('"', r#"\""#)
+      )),
+      Segment::Tag(name) => output.push_str(name),
+    }
+  }
+  Ok(())
+}
+
+// host 作为属性选择器的值输出为 css 字符串, 转义引号, 反斜杠和换行
+fn quote_host(host: &str) -> String {
+  let mut quoted = String::from("'");
+  for ch in host.chars() {
+    match ch {
+      '\\' | '\'' => {
+        quoted.push('\\');
+        quoted.push(ch);
+      }
+      '\n' | '\r' | '\x0c' => quoted.push_str(&format!("\\{:x} ", ch as u32)),
+      _ => quoted.push(ch),
+    }
+  }
+  quoted.push('\'');
+  quoted
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use insta::assert_snapshot;
+
+  #[test]
+  fn test_css_to_static() {
+    let css = CssSegments::from(vec![
+      Segment::Import("./a.css".to_string()),
+      Segment::Text(".".to_string()),
+      Segment::Prefix,
+      Segment::Text("a ".to_string()),
+      Segment::Tag("view".to_string()),
+      Segment::Text("{width:".to_string()),
+      Segment::Unit {
+        func: "rpx".to_string(),
+        value: "100".to_string(),
+      },
+      Segment::Text("}".to_string()),
+    ]);
+    let host_css = CssSegments::from(vec![
+      Segment::Text("[is=".to_string()),
+      Segment::Host,
+      Segment::Text("]{color:red}".to_string()),
+    ]);
+    let options = StaticCssOptions {
+      prefix: "sf-".to_string(),
+      host: "component-1".to_string(),
+      unit_factors: HashMap::from([("rpx".to_string(), 0.5)]),
+    };
+    assert_snapshot!(css_to_static(&css, Some(&host_css), &options).unwrap());
+
+    let error = css_to_static(&css, None, &StaticCssOptions::default()).unwrap_err();
+    assert_eq!(error, "Missing unit factor for `rpx`");
+
+    let options = StaticCssOptions {
+      host: "a']{}*{color:red}[x='\\".to_string(),
+      ..StaticCssOptions::default()
+    };
+    assert_eq!(
+      css_to_static(&host_css, None, &options).unwrap(),
+      r"[is='a\']{}*{color:red}[x=\'\\']{color:red}"
+    );
+  }
+}
@@ -1,42 +1,1026 @@
-use crate::convert_css::convert_css;
-use crate::css_to_code::{css_to_code, Css2CodeOptions};
+use crate::assets::AssetOptions;
+use crate::convert_css::{
+  convert_css, static_length, ConvertCssOptions, TagReplacement, UnitConversion,
+  CLASS_NAME_PATTERN_REGEX,
+};
+use crate::css_to_code::{
+  css_to_code, css_to_code_with_map, generate_dts, Css2CodeOptions, OutputFormat,
+};
+use crate::diagnostic::{Diagnostic, DiagnosticKind};
+use crate::js_asset::{to_js_assets, JSAsset};
+use crate::js_diagnostic::{to_js_warnings, to_napi_error, JSDiagnostic};
+use crate::js_targets::{to_targets, JSTargets};
+use crate::static_css::{css_to_static, StaticCssOptions};
+use lazy_regex::regex_is_match;
+use napi::bindgen_prelude::AsyncTask;
+use napi::{Either, Env, Task};
+use std::collections::HashMap;
 use std::string::String;
 
+/*
+ * 单位转换方式, runtime 与 factor + unit 二选一
+ * { runtime: "rpx" }         100upx => rpx(100) + "px"
+ * { factor: 16, unit: "px" } 1rem => 16px
+ */
+#[napi(object)]
+#[derive(Default)]
+pub struct JSUnitConversion {
+  pub runtime: Option<String>,
+  pub factor: Option<f64>,
+  pub unit: Option<String>,
+}
+
+/*
+ * 静态样式中替换占位符的值, 与 styleFactory(options) 的参数对应
+ * rpx 等运行时单位使用倍数代替函数  { unitFactors: { rpx: 0.5 } }
+ */
+#[napi(object)]
+#[derive(Default)]
+pub struct JSStaticCssOptions {
+  pub prefix: Option<String>,
+  pub host: Option<String>,
+  pub unit_factors: Option<HashMap<String, f64>>,
+}
+
+// 生成的 styleFactory 中已经使用的变量名, 不能作为单位转换函数名
+const RESERVED_FUNCTIONS: [&str; 6] = ["prefix", "tag", "host", "css", "options", "hostStyleText"];
+
+#[napi(object)]
+#[derive(Default)]
+pub struct StyleFactoryOptions {
+  pub targets: Option<JSTargets>,
+  pub prefix_class: Option<bool>,
+  pub replace_rpx: Option<bool>,
+  // 单位转换表, 与 replaceRpx 合并  { upx: { runtime: "rpx" }, rem: { factor: 16, unit: "px" } }
+  pub units: Option<HashMap<String, JSUnitConversion>>,
+  // 设计稿宽度, 设置后编译时把 rpx 转换成 vw, 不再调用 options.rpx
+  pub design_width: Option<f64>,
+  // 设备宽度, 与 designWidth 一起设置时把 rpx 转换成 px
+  pub device_width: Option<f64>,
+  pub replace_tag: Option<bool>,
+  // 标签选择器输出为运行时的 tag("div") 调用
+  pub runtime_tag: Option<bool>,
+  pub replace_star: Option<bool>,
+  pub replace_web_view: Option<bool>,
+  pub meta_tag: Option<String>,
+  pub unsupported_star: Option<String>,
+  pub unsupported_web_view: Option<String>,
+  // 标签替换表, 值为 "unsupported" 时输出 unsupported-<tag>, 否则作为标签别名
+  // { "web-view": "unsupported", "view": "div", "*": "unsupported" }
+  pub tag_map: Option<HashMap<String, String>>,
+  pub source_map: Option<bool>,
+  pub filename: Option<String>,
+  pub error_recovery: Option<bool>,
+  // "esm" | "cjs" | "iife", 默认 "esm"
+  pub format: Option<String>,
+  // iife 格式下挂载的全局变量名, 默认 "styleFactory"
+  pub global_name: Option<String>,
+  // 同时返回生成模块的 .d.ts
+  pub dts: Option<bool>,
+  // 同时返回样式中出现的类名
+  pub class_names: Option<bool>,
+  // 生成的模块额外导出 classes(options), 原始类名 => 运行时类名
+  pub export_classes: Option<bool>,
+  // 编译时生成 <类名>_<hash> 形式的类名, 不再使用运行时的 prefix
+  pub hash_class: Option<bool>,
+  // hashClass 使用的模板, 支持 [local] [name] [hash], 默认 "[local]_[hash]"
+  pub class_name_pattern: Option<String>,
+  // 同时返回替换了占位符的静态样式
+  pub static_css: Option<JSStaticCssOptions>,
+  // url() 中的相对地址改写为 publicPath + 相对于 filename 所在目录的路径, 目录外的资源保持原地址并返回警告
+  pub public_path: Option<String>,
+  // 同时返回 url() 引用的资源
+  pub assets: Option<bool>,
+}
+
+#[napi(object)]
+#[derive(Debug)]
+pub struct StyleFactoryResult {
+  pub code: String,
+  // 生成的 JS 到原始 CSS 的 source map, 不包含 :host 生成的 hostStyleText
+  pub map: Option<String>,
+  pub warnings: Vec<JSDiagnostic>,
+  pub dts: Option<String>,
+  pub class_names: Option<Vec<String>>,
+  pub css: Option<String>,
+  pub assets: Option<Vec<JSAsset>>,
+}
+
+// 选项错误与转换中的错误一样返回 diagnostic, kind 为 invalid-option
+pub(crate) fn invalid_option(error: napi::Error) -> Diagnostic {
+  Diagnostic::new(DiagnosticKind::InvalidOption, error.reason)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FactoryOptions {
+  pub convert: ConvertCssOptions,
+  pub format: OutputFormat,
+  pub dts: bool,
+  pub class_names: bool,
+  pub export_classes: bool,
+  pub static_css: Option<StaticCssOptions>,
+  pub assets: bool,
+}
+
+impl StyleFactoryOptions {
+  pub(crate) fn to_factory_options(&self) -> Result<FactoryOptions, napi::Error> {
+    Ok(FactoryOptions {
+      convert: self.to_convert_options()?,
+      format: self.to_output_format()?,
+      dts: self.dts.unwrap_or(false),
+      class_names: self.class_names.unwrap_or(false),
+      export_classes: self.export_classes.unwrap_or(false),
+      assets: self.assets.unwrap_or(false),
+      static_css: self.static_css.as_ref().map(|options| {
+        let defaults = StaticCssOptions::default();
+        StaticCssOptions {
+          prefix: options.prefix.clone().unwrap_or(defaults.prefix),
+          host: options.host.clone().unwrap_or(defaults.host),
+          unit_factors: options.unit_factors.clone().unwrap_or_default(),
+        }
+      }),
+    })
+  }
+
+  fn to_output_format(&self) -> Result<OutputFormat, napi::Error> {
+    let invalid = |message: String| napi::Error::new(napi::Status::InvalidArg, message);
+    match self.format.as_deref() {
+      None | Some("esm") => Ok(OutputFormat::Esm),
+      Some("cjs") => Ok(OutputFormat::Cjs),
+      Some("iife") => {
+        let global_name = self.global_name.as_deref().unwrap_or("styleFactory");
+        if !regex_is_match!(r"^[A-Za-z_$][\w$]*$", global_name) {
+          return Err(invalid(format!("Invalid globalName `{}`", global_name)));
+        }
+        Ok(OutputFormat::Iife(global_name.to_string()))
+      }
+      Some(format) => Err(invalid(format!(
+        "Invalid format `{}`, expected one of `esm`, `cjs`, `iife`",
+        format
+      ))),
+    }
+  }
+
+  fn to_convert_options(&self) -> Result<ConvertCssOptions, napi::Error> {
+    let defaults = ConvertCssOptions::default();
+    Ok(ConvertCssOptions {
+      targets: to_targets(self.targets.as_ref())?,
+      prefix_class: self.prefix_class.unwrap_or(defaults.prefix_class),
+      units: self.to_units()?,
+      replace_tag: self.replace_tag.unwrap_or(defaults.replace_tag),
+      runtime_tag: self.runtime_tag.unwrap_or(defaults.runtime_tag),
+      meta_tag: self.meta_tag.clone().unwrap_or(defaults.meta_tag),
+      tag_map: self.to_tag_map()?,
+      source_map: self.source_map.unwrap_or(defaults.source_map),
+      filename: self.filename.clone().unwrap_or(defaults.filename),
+      error_recovery: self.error_recovery.unwrap_or(defaults.error_recovery),
+      class_name_pattern: self.to_class_name_pattern()?,
+      assets: AssetOptions {
+        public_path: self.public_path.clone(),
+        ..AssetOptions::default()
+      },
+    })
+  }
+
+  // replaceRpx 决定是否保留默认的 rpx 转换, 再合并 units
+  fn to_units(&self) -> Result<HashMap<String, UnitConversion>, napi::Error> {
+    let mut units = HashMap::new();
+    if self.replace_rpx.unwrap_or(true) {
+      let invalid = |name: &str, value: f64| {
+        napi::Error::new(
+          napi::Status::InvalidArg,
+          format!("Invalid {} `{}`, expected a positive number", name, value),
+        )
+      };
+      let rpx = match (self.design_width, self.device_width) {
+        (None, None) => UnitConversion::Runtime("rpx".to_string()),
+        (None, Some(_)) => {
+          return Err(napi::Error::new(
+            napi::Status::InvalidArg,
+            "deviceWidth requires designWidth".to_string(),
+          ))
+        }
+        (Some(design), _) if design.is_nan() || design <= 0.0 => {
+          return Err(invalid("designWidth", design))
+        }
+        (Some(_), Some(device)) if device.is_nan() || device <= 0.0 => {
+          return Err(invalid("deviceWidth", device))
+        }
+        (Some(design), None) => UnitConversion::rpx_to_vw(design as f32),
+        (Some(design), Some(device)) => UnitConversion::rpx_to_px(design as f32, device as f32),
+      };
+      units.insert("rpx".to_string(), rpx);
+    }
+
+    for (unit, conversion) in self.units.iter().flatten() {
+      let invalid = |reason: &str| {
+        napi::Error::new(
+          napi::Status::InvalidArg,
+          format!("Invalid units entry `{}`: {}", unit, reason),
+        )
+      };
+      if !regex_is_match!(r"^[A-Za-z]+$", unit) {
+        return Err(invalid("unit must only contain letters"));
+      }
+      let unit = unit.to_ascii_lowercase();
+      let conversion = match conversion {
+        JSUnitConversion {
+          runtime: Some(func),
+          factor: None,
+          unit: None,
+        } => {
+          if !regex_is_match!(r"^[A-Za-z_$][\w$]*$", func)
+            || RESERVED_FUNCTIONS.contains(&func.as_str())
+          {
+            return Err(invalid("invalid runtime function name"));
+          }
+          // 标准单位会被 lightningcss 解析成 typed value, 无法插入运行时占位符
+          if static_length(1.0, &unit).is_some() {
+            return Err(invalid("standard units only support static conversion"));
+          }
+          UnitConversion::Runtime(func.clone())
+        }
+        JSUnitConversion {
+          runtime: None,
+          factor: Some(factor),
+          unit: Some(target),
+        } => {
+          if static_length(1.0, target).is_none() {
+            return Err(invalid("target unit must be a standard length unit"));
+          }
+          UnitConversion::Static {
+            factor: *factor as f32,
+            unit: target.to_ascii_lowercase(),
+          }
+        }
+        _ => return Err(invalid("expected either `runtime` or `factor` with `unit`")),
+      };
+      units.insert(unit, conversion);
+    }
+    Ok(units)
+  }
+
+  // replaceStar / unsupportedStar 等旧选项作为默认值, 再合并 tagMap
+  fn to_tag_map(&self) -> Result<HashMap<String, TagReplacement>, napi::Error> {
+    let mut tag_map = HashMap::new();
+    let legacy = [
+      ("*", self.replace_star, &self.unsupported_star),
+      (
+        "web-view",
+        self.replace_web_view,
+        &self.unsupported_web_view,
+      ),
+    ];
+    for (tag, replace, marker) in legacy {
+      if replace.unwrap_or(true) {
+        let replacement = match marker {
+          Some(marker) => TagReplacement::Unsupported(marker.clone()),
+          None => TagReplacement::unsupported(tag),
+        };
+        tag_map.insert(tag.to_string(), replacement);
+      }
+    }
+
+    for (tag, value) in self.tag_map.iter().flatten() {
+      let is_tag_name = |name: &str| regex_is_match!(r"^[A-Za-z][\w-]*$", name);
+      if !(tag == "*" || is_tag_name(tag)) || !(value == "unsupported" || is_tag_name(value)) {
+        return Err(napi::Error::new(
+          napi::Status::InvalidArg,
+          format!("Invalid tagMap entry `{}: {}`", tag, value),
+        ));
+      }
+      let replacement = match value.as_str() {
+        "unsupported" => TagReplacement::unsupported(tag),
+        alias => TagReplacement::Alias(alias.to_string()),
+      };
+      tag_map.insert(tag.clone(), replacement);
+    }
+    Ok(tag_map)
+  }
+
+  fn to_class_name_pattern(&self) -> Result<Option<String>, napi::Error> {
+    if !self.hash_class.unwrap_or(false) {
+      return Ok(None);
+    }
+    let pattern = self
+      .class_name_pattern
+      .as_deref()
+      .unwrap_or("[local]_[hash]");
+    let invalid = || {
+      napi::Error::new(
+        napi::Status::InvalidArg,
+        format!("Invalid classNamePattern `{}`", pattern),
+      )
+    };
+    // 不包含 [local] 时所有类名都会相同
+    if !pattern.contains("[local]") {
+      return Err(invalid());
+    }
+    for caps in CLASS_NAME_PATTERN_REGEX.captures_iter(pattern) {
+      if !matches!(&caps[1], "local" | "name" | "hash") {
+        return Err(invalid());
+      }
+    }
+    Ok(Some(pattern.to_string()))
+  }
+}
+
+// 只返回生成的代码, map 通过 styleFactoryResult 获取
 #[napi(js_name = "styleFactory")]
-pub fn style_factory(css_text: String) -> Result<String, napi::Error> {
-  let transform_return = convert_css(css_text)
-    .map_err(|e| napi::Error::from_reason(format!("Transform error: {}", e)))?;
+pub fn style_factory(
+  env: Env,
+  css_text: String,
+  options: Option<StyleFactoryOptions>,
+) -> Result<String, napi::Error> {
+  let build = || {
+    let factory_options = options
+      .unwrap_or_default()
+      .to_factory_options()
+      .map_err(invalid_option)?;
+    check_code_options(&factory_options)?;
+    build_style_factory(css_text, &factory_options)
+  };
+  build()
+    .map(|result| result.code)
+    .map_err(|e| to_napi_error(env, &e))
+}
+
+#[napi(js_name = "styleFactoryResult")]
+pub fn style_factory_result(
+  env: Env,
+  css_text: String,
+  options: Option<StyleFactoryOptions>,
+) -> Result<StyleFactoryResult, napi::Error> {
+  let build = || {
+    let factory_options = options
+      .unwrap_or_default()
+      .to_factory_options()
+      .map_err(invalid_option)?;
+    build_style_factory(css_text, &factory_options)
+  };
+  build().map_err(|e| to_napi_error(env, &e))
+}
+
+// 只在 styleFactoryResult 中返回的选项不能用于 styleFactory, 避免被静默忽略
+fn check_code_options(options: &FactoryOptions) -> Result<(), Diagnostic> {
+  let result_only = [
+    ("sourceMap", options.convert.source_map),
+    ("dts", options.dts),
+    ("classNames", options.class_names),
+    ("staticCss", options.static_css.is_some()),
+    ("assets", options.assets),
+  ];
+  match result_only.iter().find(|(_, enabled)| *enabled) {
+    Some((name, _)) => Err(Diagnostic::new(
+      DiagnosticKind::InvalidOption,
+      format!("`{}` is only returned by styleFactoryResult", name),
+    )),
+    None => Ok(()),
+  }
+}
 
-  let css_code = css_to_code(Css2CodeOptions {
+pub struct StyleFactoryTask {
+  css_text: String,
+  options: FactoryOptions,
+  // styleFactoryAsync 只返回代码
+  code_only: bool,
+  // compute 运行在 libuv 线程中无法创建 JS 对象, 在 reject 中再生成带 diagnostic 的错误
+  diagnostic: Option<Diagnostic>,
+}
+
+impl Task for StyleFactoryTask {
+  type Output = StyleFactoryResult;
+  type JsValue = Either<String, StyleFactoryResult>;
+
+  fn compute(&mut self) -> napi::Result<Self::Output> {
+    let css_text = std::mem::take(&mut self.css_text);
+    build_style_factory(css_text, &self.options).map_err(|e| {
+      let error = napi::Error::from_reason(e.to_string());
+      self.diagnostic = Some(e);
+      error
+    })
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+    if self.code_only {
+      Ok(Either::A(output.code))
+    } else {
+      Ok(Either::B(output))
+    }
+  }
+
+  fn reject(&mut self, env: Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+    match self.diagnostic.take() {
+      Some(diagnostic) => Err(to_napi_error(env, &diagnostic)),
+      None => Err(err),
+    }
+  }
+}
+
+#[napi(js_name = "styleFactoryAsync", ts_return_type = "Promise<string>")]
+pub fn style_factory_async(
+  env: Env,
+  css_text: String,
+  options: Option<StyleFactoryOptions>,
+) -> Result<AsyncTask<StyleFactoryTask>, napi::Error> {
+  let check = || {
+    let options = options
+      .unwrap_or_default()
+      .to_factory_options()
+      .map_err(invalid_option)?;
+    check_code_options(&options)?;
+    Ok(options)
+  };
+  let options = check().map_err(|e| to_napi_error(env, &e))?;
+  Ok(AsyncTask::new(StyleFactoryTask {
+    css_text,
+    options,
+    code_only: true,
+    diagnostic: None,
+  }))
+}
+
+#[napi(
+  js_name = "styleFactoryResultAsync",
+  ts_return_type = "Promise<StyleFactoryResult>"
+)]
+pub fn style_factory_result_async(
+  env: Env,
+  css_text: String,
+  options: Option<StyleFactoryOptions>,
+) -> Result<AsyncTask<StyleFactoryTask>, napi::Error> {
+  let options = options
+    .unwrap_or_default()
+    .to_factory_options()
+    .map_err(|e| to_napi_error(env, &invalid_option(e)))?;
+  Ok(AsyncTask::new(StyleFactoryTask {
+    css_text,
+    options,
+    code_only: false,
+    diagnostic: None,
+  }))
+}
+
+// 生成完整的结果, 未开启的选项对应的字段为 None
+pub(crate) fn build_style_factory(
+  css_text: String,
+  options: &FactoryOptions,
+) -> Result<StyleFactoryResult, Diagnostic> {
+  let transform_return =
+    convert_css(css_text, &options.convert).map_err(|e| e.context("Transform error"))?;
+
+  let classes = options
+    .export_classes
+    .then_some(transform_return.classes.as_slice());
+  let code_options = Css2CodeOptions {
     css: &transform_return.css,
-    host_css: transform_return.host_css.as_deref(),
-  });
-  Ok(css_code)
+    host_css: transform_return.host_css.as_ref(),
+    format: options.format.clone(),
+    classes,
+  };
+
+  let (code, map) = match transform_return.source_map {
+    Some(ref css_map) => {
+      let (code, map) = css_to_code_with_map(code_options, css_map)?;
+      (code, Some(map))
+    }
+    None => (css_to_code(code_options)?, None),
+  };
+
+  let css = match &options.static_css {
+    Some(static_options) => Some(
+      css_to_static(
+        &transform_return.css,
+        transform_return.host_css.as_ref(),
+        static_options,
+      )
+      .map_err(|e| Diagnostic::new(DiagnosticKind::Serialize, e).context("Static css error"))?,
+    ),
+    None => None,
+  };
+
+  Ok(StyleFactoryResult {
+    code,
+    map,
+    warnings: to_js_warnings(&transform_return.warnings),
+    dts: options
+      .dts
+      .then(|| generate_dts(&options.format, classes, &unit_functions(&options.convert))),
+    class_names: options.class_names.then(|| {
+      transform_return
+        .classes
+        .iter()
+        .map(|class| class.name.clone())
+        .collect()
+    }),
+    css,
+    assets: options
+      .assets
+      .then(|| to_js_assets(&transform_return.assets)),
+  })
+}
+
+// 配置的运行时单位转换函数, 排序去重
+fn unit_functions(options: &ConvertCssOptions) -> Vec<String> {
+  let mut functions = options
+    .units
+    .values()
+    .filter_map(|conversion| match conversion {
+      UnitConversion::Runtime(func) => Some(func.clone()),
+      UnitConversion::Static { .. } => None,
+    })
+    .collect::<Vec<_>>();
+  functions.sort();
+  functions.dedup();
+  functions
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use indoc::indoc;
   use insta::assert_snapshot;
+  use parcel_sourcemap::SourceMap;
+  use std::collections::HashMap;
+
+  fn run(
+    css_text: String,
+    options: Option<StyleFactoryOptions>,
+  ) -> Result<StyleFactoryResult, Diagnostic> {
+    let options = options.unwrap_or_default().to_factory_options().unwrap();
+    build_style_factory(css_text, &options)
+  }
+
+  fn unwrap_code(res: Result<StyleFactoryResult, Diagnostic>) -> String {
+    res.unwrap().code
+  }
 
   #[test]
   fn test_style_factory() {
     let css_text = r#".a { color: red }"#.to_string();
-    let res = style_factory(css_text);
+    let res = run(css_text, None);
     assert!(res.is_ok());
-    assert_snapshot!(res.unwrap());
+    assert_snapshot!(unwrap_code(res));
   }
 
   #[test]
   fn test_style_factory_error() {
     let css_text = r#".a color: red}"#.to_string();
-    let res = style_factory(css_text);
+    let res = run(css_text, None);
     assert!(res.is_err());
     match res {
       Err(e) => {
-        assert_snapshot!(e.reason,);
+        assert_snapshot!(e.to_string(),);
       }
       _ => panic!("Unexpected result"),
     }
   }
+
+  #[test]
+  fn test_style_factory_targets() {
+    let css_text = r#".a { color: rgb(0 0 0 / 50%); inset: 0; }"#.to_string();
+    let options = StyleFactoryOptions {
+      targets: Some(JSTargets {
+        browsers: Some(HashMap::from([("chrome".to_string(), "49".to_string())])),
+        ..JSTargets::default()
+      }),
+      ..StyleFactoryOptions::default()
+    };
+    let res = run(css_text, Some(options));
+    assert_snapshot!(unwrap_code(res));
+  }
+
+  #[test]
+  fn test_style_factory_invalid_targets() {
+    let options = StyleFactoryOptions {
+      targets: Some(JSTargets {
+        browserslist: Some(vec!["not a browser >= 1".to_string()]),
+        ..JSTargets::default()
+      }),
+      ..StyleFactoryOptions::default()
+    };
+    let res = options.to_factory_options();
+    assert!(res.is_err());
+    assert_snapshot!(res.unwrap_err().reason);
+  }
+
+  #[test]
+  fn test_style_factory_h5_options() {
+    let css_text = r#"view .a { height: 20rpx; }"#.to_string();
+    let options = StyleFactoryOptions {
+      replace_rpx: Some(false),
+      replace_tag: Some(false),
+      ..StyleFactoryOptions::default()
+    };
+    let res = run(css_text, Some(options));
+    assert_snapshot!(unwrap_code(res));
+  }
+
+  #[test]
+  fn test_style_factory_source_map() {
+    let css_text = indoc! {r#"
+      .a { content: "\""; }
+      @media (max-width: 600rpx) {
+        .b { width: 20rpx; }
+      }
+    "#}
+    .to_string();
+    let options = StyleFactoryOptions {
+      source_map: Some(true),
+      filename: Some("index.css".to_string()),
+      ..StyleFactoryOptions::default()
+    };
+    let result = run(css_text, Some(options)).unwrap();
+    assert_snapshot!(result.code);
+    let result_map = result.map.unwrap();
+    assert_snapshot!(result_map);
+    assert!(result.warnings.is_empty());
+
+    // 生成代码中 .b 的位置应该指向原始 CSS 的第 3 行
+    let mut map = SourceMap::from_json("/", &result_map).unwrap();
+    let (line, line_text) = result
+      .code
+      .lines()
+      .enumerate()
+      .find(|(_, line)| line.contains("var css"))
+      .unwrap();
+    let column = line_text.find(r#"." , prefix , "b{width"#).unwrap();
+    let mapping = map
+      .find_closest_mapping(line as u32, column as u32)
+      .unwrap();
+    let original = mapping.original.unwrap();
+    assert_eq!((original.original_line, original.original_column), (2, 2));
+    assert_eq!(map.get_source(original.source).unwrap(), "index.css");
+  }
+
+  #[test]
+  // styleFactory 只返回代码, 需要其他结果的选项报错
+  fn test_check_code_options() {
+    let options = |options: StyleFactoryOptions| options.to_factory_options().unwrap();
+    assert!(check_code_options(&options(StyleFactoryOptions {
+      error_recovery: Some(true),
+      ..StyleFactoryOptions::default()
+    }))
+    .is_ok());
+    let error = check_code_options(&options(StyleFactoryOptions {
+      source_map: Some(true),
+      ..StyleFactoryOptions::default()
+    }))
+    .unwrap_err();
+    assert_eq!(error.kind, DiagnosticKind::InvalidOption);
+    assert_eq!(
+      error.message,
+      "`sourceMap` is only returned by styleFactoryResult"
+    );
+  }
+
+  #[test]
+  fn test_style_factory_diagnostic() {
+    let css_text = indoc! {r#"
+      .a { color: red; }
+      @media (max-width: 600rpx) { .b { color: red; } } @import "./c.css";
+    "#}
+    .to_string();
+    let diagnostic = run(css_text, None).unwrap_err();
+    assert_eq!(diagnostic.kind, DiagnosticKind::Parse);
+    // lightningcss 报告的是替换 rpx 之后的位置 :1:70, 需要还原到输入的位置
+    assert_eq!((diagnostic.line, diagnostic.column), (Some(2), Some(58)));
+    assert_snapshot!(diagnostic.message);
+    assert_snapshot!(diagnostic.code_frame.unwrap());
+  }
+
+  #[test]
+  fn test_style_factory_error_recovery() {
+    let css_text = indoc! {r#"
+      .a { color: red; }
+      .b { color red; width: 10rpx; }
+    "#}
+    .to_string();
+    let options = StyleFactoryOptions {
+      error_recovery: Some(true),
+      ..StyleFactoryOptions::default()
+    };
+    let result = run(css_text, Some(options)).unwrap();
+    assert!(result.map.is_none());
+    assert_snapshot!(result.code);
+    assert_eq!(result.warnings.len(), 1);
+    let warning = &result.warnings[0];
+    assert_eq!(warning.severity, "warning");
+    assert_eq!((warning.line, warning.column), (Some(2), Some(11)));
+  }
+
+  #[test]
+  fn test_style_factory_task() {
+    let css_text = r#".a { height: 20rpx }"#.to_string();
+    let mut task = StyleFactoryTask {
+      css_text: css_text.clone(),
+      options: FactoryOptions::default(),
+      code_only: true,
+      diagnostic: None,
+    };
+    assert_eq!(
+      unwrap_code(Ok(task.compute().unwrap())),
+      unwrap_code(run(css_text, None))
+    );
+
+    let mut task = StyleFactoryTask {
+      css_text: r#".a color: red}"#.to_string(),
+      options: FactoryOptions::default(),
+      code_only: true,
+      diagnostic: None,
+    };
+    let error = task.compute().unwrap_err();
+    let diagnostic = task.diagnostic.unwrap();
+    assert_eq!(error.reason, diagnostic.to_string());
+    assert_eq!(diagnostic.kind, DiagnosticKind::Parse);
+  }
+
+  #[test]
+  fn test_style_factory_format() {
+    let css_text = r#".a { height: 20rpx }"#.to_string();
+    let options = StyleFactoryOptions {
+      format: Some("iife".to_string()),
+      global_name: Some("pageStyle".to_string()),
+      ..StyleFactoryOptions::default()
+    };
+    assert_snapshot!(unwrap_code(run(css_text, Some(options))));
+
+    let options = StyleFactoryOptions {
+      format: Some("iife".to_string()),
+      ..StyleFactoryOptions::default()
+    };
+    let error = run(
+      r#"@import "./a.css"; .a { color: red }"#.to_string(),
+      Some(options),
+    )
+    .unwrap_err();
+    assert_eq!(error.kind, DiagnosticKind::InvalidOption);
+    assert_eq!(
+      error.to_string(),
+      "format `iife` does not support @import, use `esm` or `cjs` instead"
+    );
+
+    let options = StyleFactoryOptions {
+      format: Some("umd".to_string()),
+      ..StyleFactoryOptions::default()
+    };
+    assert_snapshot!(options.to_factory_options().unwrap_err().reason);
+  }
+
+  #[test]
+  fn test_style_factory_dts() {
+    let css_text = r#".a { height: 20rpx }"#.to_string();
+    let options = StyleFactoryOptions {
+      format: Some("cjs".to_string()),
+      dts: Some(true),
+      ..StyleFactoryOptions::default()
+    };
+    let result = run(css_text, Some(options)).unwrap();
+    assert!(result.map.is_none());
+    assert_eq!(
+      result.dts,
+      Some(generate_dts(&OutputFormat::Cjs, None, &["rpx".to_string()]))
+    );
+  }
+
+  #[test]
+  fn test_style_factory_placeholder_collision() {
+    let css_text = indoc! {r#"
+      @import url('./__PREFIX__).css');
+      .a:after { content: "__RPX__(10) __HOST__"; width: 10rpx; }
+    "#}
+    .to_string();
+    assert_snapshot!(unwrap_code(run(css_text, None)));
+  }
+
+  #[test]
+  fn test_style_factory_class_names() {
+    let css_text = r#".b .a, .a:hover { color: red } :host .c { width: 10rpx }"#.to_string();
+    let options = StyleFactoryOptions {
+      class_names: Some(true),
+      export_classes: Some(true),
+      dts: Some(true),
+      ..StyleFactoryOptions::default()
+    };
+    let result = run(css_text, Some(options)).unwrap();
+    assert_eq!(result.class_names.unwrap(), vec!["a", "b", "c"]);
+    assert_snapshot!(result.code);
+    assert_snapshot!(result.dts.unwrap());
+  }
+
+  #[test]
+  fn test_style_factory_hash_class() {
+    let css_text = r#".b .a { color: red }"#.to_string();
+    let options = StyleFactoryOptions {
+      hash_class: Some(true),
+      export_classes: Some(true),
+      filename: Some("index.css".to_string()),
+      ..StyleFactoryOptions::default()
+    };
+    assert_snapshot!(unwrap_code(run(css_text, Some(options))));
+
+    let options = StyleFactoryOptions {
+      hash_class: Some(true),
+      class_name_pattern: Some("[hash]_[path]".to_string()),
+      ..StyleFactoryOptions::default()
+    };
+    assert_snapshot!(options.to_factory_options().unwrap_err().reason);
+  }
+
+  #[test]
+  fn test_style_factory_assets() {
+    let css_text = include_str!("../__fixtures__/url/index.css").to_string();
+    let options = StyleFactoryOptions {
+      filename: Some("/project/src/index.css".to_string()),
+      public_path: Some("https://cdn.com/static/".to_string()),
+      assets: Some(true),
+      ..StyleFactoryOptions::default()
+    };
+    let result = run(css_text, Some(options)).unwrap();
+    assert!(result.code.contains("url(https://cdn.com/static/abc.sbc)"));
+    let assets = result.assets.unwrap();
+    assert_eq!(assets.len(), 1);
+    assert_eq!(assets[0].url, "./abc.sbc");
+    assert_eq!(assets[0].path.as_deref(), Some("/project/src/abc.sbc"));
+    assert_eq!((assets[0].line, assets[0].column), (2, 21));
+
+    // 文件所在目录之外的资源保持原地址, 并返回警告
+    let options = StyleFactoryOptions {
+      filename: Some("/project/src/index.css".to_string()),
+      public_path: Some("https://cdn.com/static/".to_string()),
+      assets: Some(true),
+      ..StyleFactoryOptions::default()
+    };
+    let result = run(
+      ".a { background: url(../b.png); }".to_string(),
+      Some(options),
+    )
+    .unwrap();
+    assert!(result.code.contains("url(../b.png)"));
+    assert_eq!(result.warnings.len(), 1);
+    assert_eq!(result.warnings[0].kind, "invalid-option");
+    assert_eq!(result.warnings[0].severity, "warning");
+  }
+
+  #[test]
+  fn test_style_factory_runtime_tag() {
+    let css_text = include_str!("../__fixtures__/tags/index.css").to_string();
+    assert_snapshot!(unwrap_code(run(css_text.clone(), None)));
+
+    let options = StyleFactoryOptions {
+      runtime_tag: Some(true),
+      ..StyleFactoryOptions::default()
+    };
+    assert_snapshot!(unwrap_code(run(css_text, Some(options))));
+  }
+
+  #[test]
+  fn test_style_factory_tag_map() {
+    let options = StyleFactoryOptions {
+      replace_star: Some(false),
+      unsupported_web_view: Some("no-web-view".to_string()),
+      tag_map: Some(HashMap::from([
+        ("view".to_string(), "div".to_string()),
+        ("video".to_string(), "unsupported".to_string()),
+      ])),
+      ..StyleFactoryOptions::default()
+    };
+    let tag_map = options.to_factory_options().unwrap().convert.tag_map;
+    assert_eq!(
+      tag_map,
+      HashMap::from([
+        (
+          "web-view".to_string(),
+          TagReplacement::Unsupported("no-web-view".to_string())
+        ),
+        ("view".to_string(), TagReplacement::Alias("div".to_string())),
+        (
+          "video".to_string(),
+          TagReplacement::Unsupported("unsupported-video".to_string())
+        ),
+      ])
+    );
+
+    let options = StyleFactoryOptions {
+      tag_map: Some(HashMap::from([("view".to_string(), "div{}".to_string())])),
+      ..StyleFactoryOptions::default()
+    };
+    assert_snapshot!(options.to_factory_options().unwrap_err().reason);
+  }
+
+  #[test]
+  fn test_style_factory_units() {
+    let units = |entries: Vec<(&str, JSUnitConversion)>| StyleFactoryOptions {
+      units: Some(
+        entries
+          .into_iter()
+          .map(|(unit, conversion)| (unit.to_string(), conversion))
+          .collect(),
+      ),
+      ..StyleFactoryOptions::default()
+    };
+    let runtime = |func: &str| JSUnitConversion {
+      runtime: Some(func.to_string()),
+      ..JSUnitConversion::default()
+    };
+
+    let options = StyleFactoryOptions {
+      replace_rpx: Some(false),
+      ..units(vec![
+        ("HD", runtime("hd")),
+        (
+          "rem",
+          JSUnitConversion {
+            factor: Some(16.0),
+            unit: Some("px".to_string()),
+            ..JSUnitConversion::default()
+          },
+        ),
+      ])
+    };
+    let factory_options = options.to_factory_options().unwrap();
+    assert_eq!(
+      factory_options.convert.units,
+      HashMap::from([
+        ("hd".to_string(), UnitConversion::Runtime("hd".to_string())),
+        (
+          "rem".to_string(),
+          UnitConversion::Static {
+            factor: 16.0,
+            unit: "px".to_string()
+          }
+        ),
+      ])
+    );
+    let css_text = r#".a { width: 10hd; height: 2rem; margin: 10rpx; }"#.to_string();
+    assert_snapshot!(unwrap_code(build_style_factory(css_text, &factory_options)));
+
+    for options in [
+      units(vec![("vw", runtime("vw"))]),
+      units(vec![("hd", runtime("prefix"))]),
+      units(vec![("hd", JSUnitConversion::default())]),
+      units(vec![(
+        "rem",
+        JSUnitConversion {
+          factor: Some(16.0),
+          unit: Some("rpx".to_string()),
+          ..JSUnitConversion::default()
+        },
+      )]),
+    ] {
+      assert_snapshot!(options.to_factory_options().unwrap_err().reason);
+    }
+  }
+
+  #[test]
+  fn test_style_factory_design_width() {
+    let css_text = r#"@media (min-width: 750rpx) { .a { width: 375rpx } }"#.to_string();
+    let options = StyleFactoryOptions {
+      design_width: Some(750.0),
+      dts: Some(true),
+      ..StyleFactoryOptions::default()
+    };
+    let result = run(css_text.clone(), Some(options)).unwrap();
+    assert!(!result.code.contains("rpx("));
+    assert_snapshot!(result.code);
+    assert!(result
+      .dts
+      .unwrap()
+      .contains("rpx?: (value: number) => number;"));
+
+    let options = StyleFactoryOptions {
+      design_width: Some(750.0),
+      device_width: Some(375.0),
+      ..StyleFactoryOptions::default()
+    };
+    assert_snapshot!(unwrap_code(run(css_text, Some(options))));
+
+    let options = StyleFactoryOptions {
+      device_width: Some(375.0),
+      ..StyleFactoryOptions::default()
+    };
+    assert_snapshot!(options.to_factory_options().unwrap_err().reason);
+  }
+
+  #[test]
+  fn test_style_factory_static_css() {
+    let css_text = r#":host { color: red } .a view { width: 100rpx }"#.to_string();
+    let options = StyleFactoryOptions {
+      runtime_tag: Some(true),
+      static_css: Some(JSStaticCssOptions {
+        prefix: Some("sf-".to_string()),
+        host: Some("component-1".to_string()),
+        unit_factors: Some(HashMap::from([("rpx".to_string(), 0.5)])),
+      }),
+      ..StyleFactoryOptions::default()
+    };
+    let result = run(css_text.clone(), Some(options)).unwrap();
+    assert_snapshot!(result.css.unwrap());
+
+    let options = StyleFactoryOptions {
+      static_css: Some(JSStaticCssOptions::default()),
+      ..StyleFactoryOptions::default()
+    };
+    assert_snapshot!(run(css_text, Some(options)).unwrap_err().to_string());
+  }
 }
@@ -0,0 +1,142 @@
+use crate::js_asset::JSAsset;
+use crate::js_diagnostic::JSDiagnostic;
+use crate::style_factory::{build_style_factory, invalid_option, StyleFactoryOptions};
+use rayon::prelude::*;
+
+#[napi(object)]
+pub struct StyleFactoryBatchInput {
+  pub id: String,
+  pub css: String,
+  pub options: Option<StyleFactoryOptions>,
+}
+
+/*
+ * 单个输入的转换结果, 失败时 error 与同步版本抛出的错误信息一致
+ */
+#[napi(object)]
+#[derive(Debug)]
+pub struct StyleFactoryBatchResult {
+  pub id: String,
+  pub code: Option<String>,
+  pub map: Option<String>,
+  pub warnings: Option<Vec<JSDiagnostic>>,
+  pub dts: Option<String>,
+  pub class_names: Option<Vec<String>>,
+  pub css: Option<String>,
+  pub assets: Option<Vec<JSAsset>>,
+  pub error: Option<String>,
+  pub diagnostic: Option<JSDiagnostic>,
+}
+
+impl StyleFactoryBatchResult {
+  fn new(id: String) -> Self {
+    StyleFactoryBatchResult {
+      id,
+      code: None,
+      map: None,
+      warnings: None,
+      dts: None,
+      class_names: None,
+      css: None,
+      assets: None,
+      error: None,
+      diagnostic: None,
+    }
+  }
+}
+
+#[napi(js_name = "styleFactoryBatch")]
+pub fn style_factory_batch(inputs: Vec<StyleFactoryBatchInput>) -> Vec<StyleFactoryBatchResult> {
+  inputs.into_par_iter().map(run_batch_item).collect()
+}
+
+fn run_batch_item(input: StyleFactoryBatchInput) -> StyleFactoryBatchResult {
+  let mut result = StyleFactoryBatchResult::new(input.id);
+  let options = match input.options.unwrap_or_default().to_factory_options() {
+    Ok(options) => options,
+    Err(e) => {
+      let diagnostic = invalid_option(e);
+      result.error = Some(diagnostic.to_string());
+      result.diagnostic = Some(JSDiagnostic::from(&diagnostic));
+      return result;
+    }
+  };
+
+  match build_style_factory(input.css, &options) {
+    Ok(output) => {
+      result.code = Some(output.code);
+      result.map = output.map;
+      result.warnings = Some(output.warnings);
+      result.dts = output.dts;
+      result.class_names = output.class_names;
+      result.css = output.css;
+      result.assets = output.assets;
+    }
+    Err(e) => {
+      result.error = Some(e.to_string());
+      result.diagnostic = Some(JSDiagnostic::from(&e));
+    }
+  }
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::js_targets::JSTargets;
+  use insta::assert_snapshot;
+
+  fn input(id: &str, css: &str, options: Option<StyleFactoryOptions>) -> StyleFactoryBatchInput {
+    StyleFactoryBatchInput {
+      id: id.to_string(),
+      css: css.to_string(),
+      options,
+    }
+  }
+
+  #[test]
+  fn test_style_factory_batch() {
+    let results = style_factory_batch(vec![
+      input("a", ".a { height: 20rpx; }", None),
+      input("b", ".b color: red}", None),
+      input(
+        "c",
+        ".c { color: red; }",
+        Some(StyleFactoryOptions {
+          source_map: Some(true),
+          ..StyleFactoryOptions::default()
+        }),
+      ),
+      input(
+        "d",
+        ".d { color: red; }",
+        Some(StyleFactoryOptions {
+          targets: Some(JSTargets {
+            browserslist: Some(vec!["not a browser >= 1".to_string()]),
+            ..JSTargets::default()
+          }),
+          ..StyleFactoryOptions::default()
+        }),
+      ),
+    ]);
+
+    let ids = results.iter().map(|r| r.id.as_str()).collect::<Vec<_>>();
+    assert_eq!(ids, vec!["a", "b", "c", "d"]);
+
+    assert_snapshot!(results[0].code.as_ref().unwrap());
+    assert!(results[0].error.is_none());
+
+    assert!(results[1].code.is_none());
+    assert_snapshot!(results[1].error.as_ref().unwrap());
+    assert_eq!(results[1].diagnostic.as_ref().unwrap().kind, "parse");
+
+    assert!(results[2].map.is_some());
+    assert!(results[2].warnings.as_ref().unwrap().is_empty());
+
+    assert!(results[3].error.is_some());
+    assert_eq!(
+      results[3].diagnostic.as_ref().unwrap().kind,
+      "invalid-option"
+    );
+  }
+}
@@ -1,30 +1,72 @@
+use crate::diagnostic::{Diagnostic, DiagnosticKind};
 use crate::options::{get_minify_options, get_parser_options, get_printer_options};
+use lightningcss::stylesheet::ParserOptions;
 use lightningcss::stylesheet::{PrinterOptions, StyleSheet};
-use std::error::Error;
+use lightningcss::targets::Targets;
+use std::sync::{Arc, RwLock};
 
 #[derive(Debug)]
 pub struct TransformCssOptions<'a> {
   pub input: &'a str,
   pub minify: bool,
+  pub targets: Targets,
+  // 跳过无效的规则和声明, 并收集为 warnings
+  pub error_recovery: bool,
 }
 
-pub fn transform_css(options: TransformCssOptions) -> Result<String, Box<dyn Error + '_>> {
-  // 将 input 的所有权转移到 parse 方法中
-  let mut stylesheet = StyleSheet::parse(options.input, get_parser_options())?;
-
-  stylesheet.minify(get_minify_options())?;
+#[derive(Debug)]
+pub struct TransformCssResult {
+  pub code: String,
+  pub warnings: Vec<Diagnostic>,
+}
 
-  let output = stylesheet.to_css(PrinterOptions {
-    minify: options.minify, // 根据传入参数决定是否压缩输出
-    ..get_printer_options()
+pub fn transform_css(options: TransformCssOptions) -> Result<TransformCssResult, Diagnostic> {
+  let warnings = Arc::new(RwLock::new(Vec::new()));
+  let parser_options = ParserOptions {
+    error_recovery: options.error_recovery,
+    warnings: options.error_recovery.then(|| warnings.clone()),
+    ..get_parser_options()
+  };
+  let mut stylesheet = StyleSheet::parse(options.input, parser_options).map_err(|e| {
+    Diagnostic::from_css_error(DiagnosticKind::Parse, &e).with_code_frame(options.input)
   })?;
 
-  Ok(output.code)
+  let warnings = warnings
+    .read()
+    .unwrap()
+    .iter()
+    .map(|e| {
+      Diagnostic::from_css_error(DiagnosticKind::Parse, e)
+        .with_code_frame(options.input)
+        .warning()
+    })
+    .collect();
+
+  stylesheet
+    .minify(get_minify_options(options.targets))
+    .map_err(|e| {
+      Diagnostic::from_css_error(DiagnosticKind::Minify, &e).with_code_frame(options.input)
+    })?;
+
+  let output = stylesheet
+    .to_css(PrinterOptions {
+      minify: options.minify, // 根据传入参数决定是否压缩输出
+      ..get_printer_options(options.targets)
+    })
+    .map_err(|e| {
+      Diagnostic::from_css_error(DiagnosticKind::Serialize, &e).with_code_frame(options.input)
+    })?;
+
+  Ok(TransformCssResult {
+    code: output.code,
+    warnings,
+  })
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::options::{get_targets, resolve_targets};
   use indoc::indoc;
   use insta::assert_snapshot;
 
@@ -39,9 +81,11 @@ mod tests {
       }
       "#},
       minify: false,
+      targets: get_targets(),
+      error_recovery: false,
     };
 
-    let result = transform_css(options).unwrap();
+    let result = transform_css(options).unwrap().code;
     assert_snapshot!(result)
   }
 
@@ -50,9 +94,11 @@ mod tests {
     let options = TransformCssOptions {
       input: "body { color: red; .a { color: blue } }",
       minify: false,
+      targets: get_targets(),
+      error_recovery: false,
     };
 
-    let result = transform_css(options).unwrap();
+    let result = transform_css(options).unwrap().code;
     assert_snapshot!(result)
   }
 
@@ -61,6 +107,8 @@ mod tests {
     let options = TransformCssOptions {
       input: "invalid-css",
       minify: true,
+      targets: get_targets(),
+      error_recovery: false,
     };
 
     let result = transform_css(options);
@@ -75,9 +123,11 @@ mod tests {
     let options = TransformCssOptions {
       input: "",
       minify: true,
+      targets: get_targets(),
+      error_recovery: false,
     };
 
-    let result = transform_css(options).unwrap();
+    let result = transform_css(options).unwrap().code;
     assert_snapshot!(result)
   }
 
@@ -86,9 +136,65 @@ mod tests {
     let options = TransformCssOptions {
       input: "h1 { font-size: 20px; } p { margin: 10px; }",
       minify: true,
+      targets: get_targets(),
+      error_recovery: false,
     };
 
-    let result = transform_css(options).unwrap();
+    let result = transform_css(options).unwrap().code;
     assert_snapshot!(result)
   }
+
+  #[test]
+  fn test_transform_css_targets() {
+    let input = ".a { display: flex; user-select: none; }";
+    let legacy_targets = resolve_targets(Some(&["ie 10".to_string()]), None).unwrap();
+    let legacy = transform_css(TransformCssOptions {
+      input,
+      minify: true,
+      targets: legacy_targets,
+      error_recovery: false,
+    })
+    .unwrap()
+    .code;
+    assert_snapshot!(legacy);
+
+    let modern_targets = resolve_targets(Some(&["chrome >= 120".to_string()]), None).unwrap();
+    let modern = transform_css(TransformCssOptions {
+      input,
+      minify: true,
+      targets: modern_targets,
+      error_recovery: false,
+    })
+    .unwrap()
+    .code;
+    assert_snapshot!(modern);
+  }
+
+  #[test]
+  fn test_transform_css_error_recovery() {
+    let input = ".a { width 1px; color: red; }
+.b { height: 1px }";
+    let options = TransformCssOptions {
+      input,
+      minify: true,
+      targets: get_targets(),
+      error_recovery: true,
+    };
+
+    let result = transform_css(options).unwrap();
+    assert_snapshot!(result.code);
+    assert_eq!(result.warnings.len(), 1);
+    let warning = &result.warnings[0];
+    assert_eq!((warning.line, warning.column), (Some(1), Some(11)));
+    assert_snapshot!(warning.code_frame.as_ref().unwrap());
+
+    // 未开启 error_recovery 时, 整个文件解析失败
+    let result = transform_css(TransformCssOptions {
+      input,
+      minify: true,
+      targets: get_targets(),
+      error_recovery: false,
+    });
+    assert!(result.is_err());
+  }
 }